@@ -16,7 +16,7 @@ enum Nucleobase {
     Thymine,
 }
 
-#[derive(Copy, Clone, Debug, Ord, Eq)]
+#[derive(Copy, Clone, Debug, Eq)]
 struct Primer {
     length: u8,
     sequence: [Nucleobase; 31],
@@ -39,7 +39,13 @@ impl PartialEq<Primer> for Primer {
 
 impl PartialOrd for Primer {
     fn partial_cmp(&self, other: &Primer) -> Option<Ordering> {
-        Some(self.as_bytes().cmp(other.as_bytes()))
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Primer {
+    fn cmp(&self, other: &Primer) -> Ordering {
+        self.as_bytes().cmp(other.as_bytes())
     }
 }
 
@@ -58,6 +64,8 @@ impl std::convert::AsRef<[u8]> for Primer {
 struct CustomDist;
 
 impl Distribution<Primer> for CustomDist {
+    // Arms are matched in order, so endpoints shared by two ranges go to the first.
+    #[allow(overlapping_range_endpoints)]
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Primer {
         let length = 22 + rng.gen::<u8>() % 4;
         let mut sequence = [Nucleobase::Adenine; 31];
@@ -116,7 +124,7 @@ macro_rules! gen_bench {
             #[bench]
             fn find_primer_naive_sortedvec(b: &mut test::Bencher) {
                 // create primer set
-                let mut rng = SmallRng::from_seed(SEED);
+                let rng = SmallRng::from_seed(SEED);
                 let dataset: SortedPrimerVec = rng.sample_iter(&CustomDist).take(SAMPLE_SIZE).collect();
                 let test_val = dataset[SAMPLE_SIZE/ 2 - 1];
 
@@ -131,7 +139,7 @@ macro_rules! gen_bench {
                 assert_eq!(std::mem::size_of::<Primer>(), 32);
 
                 // create primer set
-                let mut rng = SmallRng::from_seed(SEED);
+                let rng = SmallRng::from_seed(SEED);
                 let dataset: SortedSlicePrimerVec = rng.sample_iter(&CustomDist).take(SAMPLE_SIZE).collect();
                 let test_val = dataset[SAMPLE_SIZE/ 2 - 1];
 
@@ -140,10 +148,32 @@ macro_rules! gen_bench {
                 });
             }
 
+            #[bench]
+            fn find_primers_one_by_one_sortedvec(b: &mut test::Bencher) {
+                let rng = SmallRng::from_seed(SEED);
+                let dataset: SortedSlicePrimerVec = rng.sample_iter(&CustomDist).take(SAMPLE_SIZE).collect();
+                let queries: Vec<Primer> = dataset.iter().step_by(7).cloned().collect();
+
+                b.iter(|| {
+                    queries.iter().map(|primer| dataset.find(primer)).count()
+                });
+            }
+
+            #[bench]
+            fn find_primers_batched_sortedvec(b: &mut test::Bencher) {
+                let rng = SmallRng::from_seed(SEED);
+                let dataset: SortedSlicePrimerVec = rng.sample_iter(&CustomDist).take(SAMPLE_SIZE).collect();
+                let queries: Vec<Primer> = dataset.iter().step_by(7).cloned().collect();
+
+                b.iter(|| {
+                    dataset.find_many(&queries)
+                });
+            }
+
             #[bench]
             fn find_primer_hashset(b: &mut test::Bencher) {
                 // create primer set
-                let mut rng = SmallRng::from_seed(SEED);
+                let rng = SmallRng::from_seed(SEED);
                 let dataset: std::collections::HashSet<_> = rng.sample_iter(&CustomDist).take(SAMPLE_SIZE).collect();
                 let test_val = dataset.iter().skip(SAMPLE_SIZE/ 2 - 1).next().unwrap();
 
@@ -34,6 +34,48 @@ macro_rules! gen_bench {
 
                     b.iter(|| sortedvec.find(&piv.borrow()));
                 }
+
+                #[bench]
+                fn find_scattered_sortedvec(b: &mut test::Bencher) {
+                    let vec: Vec<_> = (0u32..$x).map($gen).collect();
+                    let sortedvec = super::SortedVec::from(vec);
+                    let probes: Vec<_> = (0u32..1024)
+                        .map(|i| ($gen)(i.wrapping_mul(2_654_435_761) % $x))
+                        .collect();
+
+                    b.iter(|| probes.iter().filter(|piv| sortedvec.contains(&(*piv).borrow())).count());
+                }
+
+                #[bench]
+                fn find_scattered_branchless(b: &mut test::Bencher) {
+                    let vec: Vec<_> = (0u32..$x).map($gen).collect();
+                    let sortedvec = super::BranchlessSortedVec::from(vec);
+                    let probes: Vec<_> = (0u32..1024)
+                        .map(|i| ($gen)(i.wrapping_mul(2_654_435_761) % $x))
+                        .collect();
+
+                    b.iter(|| probes.iter().filter(|piv| sortedvec.contains(&(*piv).borrow())).count());
+                }
+
+                #[bench]
+                fn find_scattered_frozen(b: &mut test::Bencher) {
+                    let vec: Vec<_> = (0u32..$x).map($gen).collect();
+                    let frozen = super::SortedVec::from(vec).freeze();
+                    let probes: Vec<_> = (0u32..1024)
+                        .map(|i| ($gen)(i.wrapping_mul(2_654_435_761) % $x))
+                        .collect();
+
+                    b.iter(|| probes.iter().filter(|piv| frozen.contains(&(*piv).borrow())).count());
+                }
+
+                #[bench]
+                fn find_frozen(b: &mut test::Bencher) {
+                    let vec: Vec<_> = (0u32..$x).map($gen).collect();
+                    let frozen = super::SortedVec::from(vec).freeze();
+                    let piv = ($gen)(($x / 2).saturating_sub(1));
+
+                    b.iter(|| frozen.find(&piv.borrow()));
+                }
             }
         )*
     }
@@ -47,6 +89,13 @@ mod string_bench {
         }
     }
 
+    sortedvec::sortedvec! {
+        struct BranchlessSortedVec {
+            fn derive_key(x: &String) -> &str { &x[..] }
+            options { search = branchless }
+        }
+    }
+
     gen_bench!(
         |x: u32| format!("{:04}", x),
 
@@ -63,7 +112,9 @@ mod string_bench {
         s0200;200u32,
         s0350;350u32,
         s0500;500u32,
-        s1000;1000u32
+        s1000;1000u32,
+        s100000;100_000u32,
+        s1000000;1_000_000u32
     );
 }
 
@@ -75,6 +126,13 @@ mod int_bench {
         }
     }
 
+    sortedvec::sortedvec! {
+        struct BranchlessSortedVec {
+            fn derive_key(x: &u32) -> u32 { *x }
+            options { search = branchless }
+        }
+    }
+
     gen_bench!(
         |x: u32| x,
 
@@ -91,6 +149,8 @@ mod int_bench {
         s0200;200u32,
         s0350;350u32,
         s0500;500u32,
-        s1000;1000u32
+        s1000;1000u32,
+        s100000;100_000u32,
+        s1000000;1_000_000u32
     );
 }
@@ -0,0 +1,338 @@
+//! Derive macro for the `sortedvec` crate. It is re-exported by `sortedvec` when its `derive`
+//! feature is enabled, and should be used through that crate.
+//!
+//! The macro turns a newtype around a `Vec` into a sorted vector:
+//!
+//! ```rust
+//! use sortedvec::SortedVec;
+//!
+//! fn by_id(entry: &(u64, String)) -> u64 {
+//!     entry.0
+//! }
+//!
+//! #[derive(SortedVec, Debug, Clone)]
+//! #[sortedvec(key = "by_id", key_type = "u64", unique)]
+//! pub struct Entries(Vec<(u64, String)>);
+//!
+//! let entries: Entries = vec![(2, "b".to_owned()), (1, "a".to_owned())].into();
+//!
+//! assert_eq!(Some(&(2, "b".to_owned())), entries.find(&2));
+//! ```
+//!
+//! The following arguments are accepted by the `sortedvec` attribute:
+//! - `key = "path"`: the key derivation function, of type `fn(&T) -> K`,
+//! - `key_type = "K"`: the key type returned by that function. Since a derive macro cannot see
+//!   the signature of the function, it must be given along with `key`,
+//! - `extractor = "X"`: a type that implements `KeyExtractor` with `Value = T`, as an alternative
+//!   to `key` and `key_type` that names the key type only once, in the `KeyExtractor` impl,
+//! - `unique`, `unique = "first" | "last"`, `merge = "path"`, `stable`,
+//!   `order = "ascending" | "descending"`, `search = "binary" | "branchless" | "interpolation"`,
+//!   `partial_eq` and `debug_map`: the options of the `sortedvec!` macro with the same names,
+//! - `crate = "path"`: the path of the `sortedvec` crate, for when the dependency is renamed.
+//!   Defaults to `::sortedvec`.
+//!
+//! ```rust
+//! use sortedvec::{KeyExtractor, SortedVec};
+//!
+//! struct ByLength;
+//!
+//! impl KeyExtractor for ByLength {
+//!     type Value = String;
+//!     type Key = usize;
+//!
+//!     fn key(word: &String) -> usize {
+//!         word.len()
+//!     }
+//! }
+//!
+//! #[derive(SortedVec)]
+//! #[sortedvec(extractor = "ByLength", stable)]
+//! pub struct Words(Vec<String>);
+//!
+//! let words: Words = vec!["three".to_owned(), "one".to_owned(), "two".to_owned()].into();
+//!
+//! assert_eq!(Some(&"one".to_owned()), words.find(&3));
+//! ```
+//!
+//! The macro expands to the `impl` form of `sortedvec!` for existing newtypes, so that the
+//! generated type behaves exactly like one generated by `sortedvec!` with the same options. It
+//! implements `Default`, so that trait cannot be derived as well. Neither can `Debug` when
+//! `debug_map` is given. Type parameters can only have trait bounds.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, ToTokens};
+use syn::spanned::Spanned;
+use syn::{
+    Data, DeriveInput, Error, Fields, GenericArgument, GenericParam, Ident, LitStr, Path,
+    PathArguments, Result, Token, TraitBoundModifier, Type, TypeParamBound, WherePredicate,
+};
+
+/// Implements a sorted vector on a newtype `struct Name(Vec<T>)`. See the crate documentation
+/// for the accepted attribute arguments.
+#[proc_macro_derive(SortedVec, attributes(sortedvec))]
+pub fn derive_sorted_vec(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+#[derive(Default)]
+struct Options {
+    krate: Option<Path>,
+    key: Option<Path>,
+    key_type: Option<Type>,
+    extractor: Option<Type>,
+    options: Vec<TokenStream2>,
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2> {
+    let options = parse_options(&input)?;
+    let val = value_type(&input)?;
+    let krate = options
+        .krate
+        .unwrap_or_else(|| syn::parse_quote!(::sortedvec));
+    let (key_type, key) = match (options.key, options.key_type, options.extractor) {
+        (Some(key), Some(key_type), None) => (quote!(#key_type), quote!(#key)),
+        (None, None, Some(extractor)) => (
+            quote!(<#extractor as #krate::KeyExtractor>::Key),
+            quote!(<#extractor as #krate::KeyExtractor>::key),
+        ),
+        (Some(_), None, None) => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "missing `#[sortedvec(key_type = \"..\")]` attribute",
+            ))
+        }
+        (None, Some(_), None) => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "missing `#[sortedvec(key = \"..\")]` attribute",
+            ))
+        }
+        (None, None, None) => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "missing `#[sortedvec(key = \"..\", key_type = \"..\")]` or \
+                 `#[sortedvec(extractor = \"..\")]` attribute",
+            ))
+        }
+        (_, _, Some(_)) => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "`extractor` cannot be combined with `key` or `key_type`",
+            ))
+        }
+    };
+
+    // The `impl` form of `sortedvec!` takes bare parameters, with all bounds in the `where`
+    // clause, one trait per predicate.
+    let mut params = Vec::new();
+    let mut predicates = Vec::new();
+    for param in &input.generics.params {
+        match param {
+            GenericParam::Lifetime(def) => {
+                if !def.bounds.is_empty() {
+                    return Err(Error::new_spanned(
+                        &def.bounds,
+                        "lifetime bounds are not supported",
+                    ));
+                }
+                params.push(def.lifetime.to_token_stream());
+            }
+            GenericParam::Type(def) => {
+                if let Some(default) = &def.default {
+                    return Err(Error::new_spanned(
+                        default,
+                        "default type parameters are not supported",
+                    ));
+                }
+                let ident = &def.ident;
+                for bound in &def.bounds {
+                    let bound = trait_bound(bound)?;
+                    predicates.push(quote!(#ident: #bound));
+                }
+                params.push(ident.to_token_stream());
+            }
+            GenericParam::Const(def) => {
+                return Err(Error::new_spanned(
+                    def,
+                    "const parameters are not supported",
+                ));
+            }
+        }
+    }
+    if let Some(clause) = &input.generics.where_clause {
+        for predicate in &clause.predicates {
+            match predicate {
+                WherePredicate::Type(predicate) if predicate.lifetimes.is_none() => {
+                    let bounded = &predicate.bounded_ty;
+                    for bound in &predicate.bounds {
+                        let bound = trait_bound(bound)?;
+                        predicates.push(quote!(#bounded: #bound));
+                    }
+                }
+                _ => {
+                    return Err(Error::new_spanned(
+                        predicate,
+                        "only trait bounds are supported",
+                    ))
+                }
+            }
+        }
+    }
+
+    let name = &input.ident;
+    let generics = if params.is_empty() {
+        quote!()
+    } else {
+        quote!(<#(#params),*>)
+    };
+    let where_clause = if predicates.is_empty() {
+        quote!()
+    } else {
+        quote!(where #(#predicates),*)
+    };
+    let options = &options.options;
+
+    Ok(quote! {
+        #krate::sortedvec! {
+            impl #name #generics #where_clause {
+                fn derive_key(val: &#val) -> #key_type { #key(val) }
+                options { #(#options),* }
+            }
+        }
+    })
+}
+
+/// Returns the trait of a bound, which must be a plain trait bound.
+fn trait_bound(bound: &TypeParamBound) -> Result<&Path> {
+    match bound {
+        TypeParamBound::Trait(bound)
+            if bound.lifetimes.is_none() && matches!(bound.modifier, TraitBoundModifier::None) =>
+        {
+            Ok(&bound.path)
+        }
+        _ => Err(Error::new_spanned(bound, "only trait bounds are supported")),
+    }
+}
+
+fn parse_options(input: &DeriveInput) -> Result<Options> {
+    let mut options = Options::default();
+
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("sortedvec"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("crate") {
+                let lit: LitStr = meta.value()?.parse()?;
+                options.krate = Some(lit.parse()?);
+            } else if meta.path.is_ident("key") {
+                let lit: LitStr = meta.value()?.parse()?;
+                options.key = Some(lit.parse()?);
+            } else if meta.path.is_ident("key_type") {
+                let lit: LitStr = meta.value()?.parse()?;
+                options.key_type = Some(lit.parse()?);
+            } else if meta.path.is_ident("extractor") {
+                let lit: LitStr = meta.value()?.parse()?;
+                options.extractor = Some(lit.parse()?);
+            } else if meta.path.is_ident("unique") && meta.input.peek(Token![=]) {
+                let lit: LitStr = meta.value()?.parse()?;
+                options.options.push(match lit.value().as_str() {
+                    "first" => quote!(unique = first),
+                    "last" => quote!(unique = last),
+                    _ => {
+                        return Err(Error::new_spanned(
+                            lit,
+                            "expected `\"first\"` or `\"last\"`",
+                        ))
+                    }
+                });
+            } else if meta.path.is_ident("unique") {
+                options.options.push(quote!(unique));
+            } else if meta.path.is_ident("merge") {
+                let lit: LitStr = meta.value()?.parse()?;
+                let path: Path = lit.parse()?;
+                options.options.push(quote!(merge = #path));
+            } else if meta.path.is_ident("stable")
+                || meta.path.is_ident("partial_eq")
+                || meta.path.is_ident("debug_map")
+            {
+                let path = &meta.path;
+                options.options.push(quote!(#path));
+            } else if meta.path.is_ident("search") {
+                let lit: LitStr = meta.value()?.parse()?;
+                let strategy = match lit.value().as_str() {
+                    "binary" | "branchless" | "interpolation" => {
+                        Ident::new(&lit.value(), lit.span())
+                    }
+                    _ => {
+                        return Err(Error::new_spanned(
+                            lit,
+                            "expected `\"binary\"`, `\"branchless\"` or `\"interpolation\"`",
+                        ))
+                    }
+                };
+                options.options.push(quote!(search = #strategy));
+            } else if meta.path.is_ident("order") {
+                let lit: LitStr = meta.value()?.parse()?;
+                options.options.push(match lit.value().as_str() {
+                    "ascending" => quote!(order = ascending),
+                    "descending" => quote!(order = descending),
+                    _ => {
+                        return Err(Error::new_spanned(
+                            lit,
+                            "expected `\"ascending\"` or `\"descending\"`",
+                        ))
+                    }
+                });
+            } else {
+                return Err(meta.error(format!(
+                    "unknown sortedvec argument `{}`",
+                    meta.path.to_token_stream()
+                )));
+            }
+            Ok(())
+        })?;
+    }
+
+    Ok(options)
+}
+
+/// Extracts `T` from a struct of the form `struct Name(Vec<T>)`.
+fn value_type(input: &DeriveInput) -> Result<&Type> {
+    let error = || {
+        Error::new(
+            input.span(),
+            "`SortedVec` can only be derived for structs of the form `struct Name(Vec<T>)`",
+        )
+    };
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => fields,
+            _ => return Err(error()),
+        },
+        _ => return Err(error()),
+    };
+    let path = match &fields.unnamed[0].ty {
+        Type::Path(ty) if ty.qself.is_none() => &ty.path,
+        _ => return Err(error()),
+    };
+    let segment = path.segments.last().ok_or_else(error)?;
+    if segment.ident != "Vec" {
+        return Err(error());
+    }
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) if args.args.len() == 1 => match &args.args[0] {
+            GenericArgument::Type(ty) => Ok(ty),
+            _ => Err(error()),
+        },
+        _ => Err(error()),
+    }
+}
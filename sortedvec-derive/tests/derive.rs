@@ -0,0 +1,118 @@
+use sortedvec::{sortedvec, KeyExtractor, SortedVec};
+
+fn first(x: &(u32, char)) -> u32 {
+    x.0
+}
+
+fn name_of<T>(x: &(&'static str, T)) -> &'static str {
+    x.0
+}
+
+#[derive(SortedVec, Debug, Clone)]
+#[sortedvec(key = "first", key_type = "u32")]
+struct Plain(Vec<(u32, char)>);
+
+#[derive(SortedVec, Debug, Clone)]
+#[sortedvec(key = "first", key_type = "u32", unique, order = "descending")]
+struct Unique(Vec<(u32, char)>);
+
+fn keep_max(kept: &mut (u32, char), dup: (u32, char)) {
+    kept.1 = std::cmp::max(kept.1, dup.1);
+}
+
+#[derive(SortedVec, Debug, Clone)]
+#[sortedvec(key = "first", key_type = "u32", merge = "keep_max")]
+struct Merged(Vec<(u32, char)>);
+
+#[derive(SortedVec, Debug, Clone)]
+#[sortedvec(key = "first", key_type = "u32", unique = "first")]
+struct First(Vec<(u32, char)>);
+
+#[derive(SortedVec, Debug, Clone)]
+#[sortedvec(key = "first", key_type = "u32", search = "branchless")]
+struct Branchless(Vec<(u32, char)>);
+
+#[derive(SortedVec, Debug)]
+#[sortedvec(key = "name_of::<T>", key_type = "&'static str")]
+struct Named<T>(Vec<(&'static str, T)>)
+where
+    T: std::fmt::Debug;
+
+struct ByFirst;
+
+impl KeyExtractor for ByFirst {
+    type Value = (u32, char);
+    type Key = u32;
+
+    fn key(x: &(u32, char)) -> u32 {
+        x.0
+    }
+}
+
+#[derive(SortedVec, Debug, Clone)]
+#[sortedvec(extractor = "ByFirst", order = "descending")]
+struct Extracted(Vec<(u32, char)>);
+
+mod renamed {
+    pub use ::sortedvec as sorted;
+}
+
+#[derive(SortedVec, Debug)]
+#[sortedvec(
+    crate = "renamed::sorted",
+    key = "name_of::<T>",
+    key_type = "&'static str"
+)]
+struct Bounded<T: Clone + PartialEq>(Vec<(&'static str, T)>);
+
+sortedvec! {
+    #[derive(Debug, Clone)]
+    struct Reference {
+        fn derive_key(x: &(u32, char)) -> u32 { x.0 }
+    }
+}
+
+#[test]
+fn matches_macro() {
+    let input = vec![(3, 'a'), (1, 'b'), (2, 'c'), (5, 'd'), (4, 'e')];
+    let mut derived = Plain::from(input.clone());
+    let mut reference = Reference::from(input);
+    assert_eq!(&reference[..], &derived[..]);
+
+    derived.insert((0, 'f'));
+    reference.insert((0, 'f'));
+    assert_eq!(reference.position(&4), derived.position(&4));
+    assert_eq!(reference.remove(&2), derived.remove(&2));
+    assert_eq!(reference.split_off(2)[..], derived.split_off(2)[..]);
+    assert_eq!(reference.pop(), derived.pop());
+    assert_eq!(Vec::from(reference), Vec::from(derived));
+}
+
+#[test]
+fn options_and_generics() {
+    let unique: Unique = vec![(1, 'a'), (2, 'b'), (1, 'c')].into_iter().collect();
+    assert_eq!(&[(2, 'b'), (1, 'c')][..], &unique[..]);
+
+    let mut merged = Merged::from(vec![(1, 'a'), (2, 'b'), (1, 'c')]);
+    merged.insert((2, 'a'));
+    assert_eq!(&[(1, 'c'), (2, 'b')][..], &merged[..]);
+
+    let first = First::from(vec![(1, 'a'), (2, 'b'), (1, 'c')]);
+    assert_eq!(&[(1, 'a'), (2, 'b')][..], &first[..]);
+
+    let branchless = Branchless::from(vec![(3, 'a'), (1, 'b'), (3, 'c')]);
+    assert_eq!(Ok(1), branchless.position(&3));
+    assert_eq!(Err(1), branchless.position(&2));
+
+    let mut named = Named::default();
+    named.extend(vec![("b", 2.0), ("a", 1.0)]);
+    assert!(named.contains(&"a"));
+    assert_eq!(Some(&("b", 2.0)), named.find(&"b"));
+
+    let extracted = Extracted::from(vec![(1, 'a'), (3, 'b'), (2, 'c')]);
+    assert_eq!(&[(3, 'b'), (2, 'c'), (1, 'a')][..], &extracted[..]);
+    assert_eq!(Ok(2), extracted.position(&1));
+
+    let bounded = Bounded::from(vec![("b", 2), ("a", 1)]);
+    assert_eq!(Some(&("a", 1)), bounded.find(&"a"));
+}
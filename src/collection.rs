@@ -0,0 +1,80 @@
+//! A trait that is implemented by all sorted vectors, so that functions can accept any of them.
+//!
+//! ```rust
+//! use sortedvec::{sortedvec, sortedvec_slicekey, SortedCollection};
+//!
+//! sortedvec! {
+//!     struct Numbers {
+//!         fn derive_key(x: &u32) -> u32 { *x }
+//!     }
+//! }
+//!
+//! sortedvec_slicekey! {
+//!     struct Words {
+//!         fn derive_key(x: &String) -> &[u8] { x.as_bytes() }
+//!     }
+//! }
+//!
+//! fn insert_missing<K, C>(collection: &mut C, key: &K, val: C::Value)
+//! where
+//!     K: ?Sized,
+//!     C: SortedCollection<K>,
+//! {
+//!     if !collection.contains(key) {
+//!         collection.insert(val);
+//!     }
+//! }
+//!
+//! let mut numbers = Numbers::default();
+//! insert_missing(&mut numbers, &5, 5);
+//! insert_missing(&mut numbers, &5, 5);
+//! assert_eq!(1, numbers.len());
+//!
+//! let mut words = Words::default();
+//! insert_missing(&mut words, b"hi".as_ref(), "hi".to_owned());
+//! assert!(SortedCollection::contains(&words, b"hi".as_ref()));
+//! ```
+
+/// Operations shared by all sorted vectors, whether generated by `sortedvec!`,
+/// `sortedvec_slicekey!`, the `SortedVec` derive macro or the generic [`SortedVec`] struct.
+///
+/// The key type is a type parameter rather than an associated type, because keys may borrow
+/// from the values they are derived from. A sorted vector on `&str` keys implements
+/// `SortedCollection<&'a str>` for every lifetime `'a`. Types generated by
+/// `sortedvec_slicekey!` on `&[K]` keys implement `SortedCollection<[K]>`.
+///
+/// The methods behave like the inherent methods with the same names.
+///
+/// [`SortedVec`]: struct.SortedVec.html
+pub trait SortedCollection<Key: ?Sized> {
+    /// The type of the values stored in the collection.
+    type Value;
+
+    /// Tries to find an element in the collection with the given key, and return
+    /// its index when found. When it is not present, the index where it should be
+    /// inserted is returned.
+    fn position(&self, key: &Key) -> Result<usize, usize>;
+
+    /// Tries to find an element in the collection with the given key.
+    fn find(&self, key: &Key) -> Option<&Self::Value>;
+
+    /// Checks whether there is a value with that key in the collection.
+    fn contains(&self, key: &Key) -> bool {
+        self.position(key).is_ok()
+    }
+
+    /// Inserts a new value into the collection, maintaining the internal order invariant.
+    fn insert(&mut self, val: Self::Value);
+
+    /// Removes and returns a single value from the collection with the given key,
+    /// if it exists.
+    fn remove(&mut self, key: &Key) -> Option<Self::Value>;
+
+    /// Returns the number of values in the collection.
+    fn len(&self) -> usize;
+
+    /// Returns `true` if the collection contains no values.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
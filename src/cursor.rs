@@ -0,0 +1,164 @@
+//! Cursors that walk sorted vectors from a position found by key, so that incremental
+//! processing can resume where it left off instead of searching from scratch.
+//!
+//! A cursor sits between two values, like a text cursor. The value after the cursor is the
+//! current value, which is returned by `peek` and `next`. A cursor at the end of the collection
+//! has no current value.
+//!
+//! ```rust
+//! use sortedvec::sortedvec;
+//!
+//! sortedvec! {
+//!     struct Events {
+//!         fn derive_key(event: &(u64, &'static str)) -> u64 { event.0 }
+//!     }
+//! }
+//!
+//! let mut events = Events::from(vec![(10, "start"), (20, "tick"), (40, "stop")]);
+//!
+//! let mut cursor = events.cursor_at(&15);
+//! assert_eq!(Some(&(20, "tick")), cursor.next());
+//! assert_eq!(Some(&(40, "stop")), cursor.peek());
+//!
+//! let mut cursor = events.cursor_mut_at(&20);
+//! assert_eq!(Some((20, "tick")), cursor.remove_current());
+//! assert_eq!(Ok(()), cursor.insert_before((30, "tock")));
+//! assert_eq!(Err((50, "late")), cursor.insert_after((50, "late")));
+//! assert_eq!(Some(&(40, "stop")), cursor.next());
+//!
+//! assert_eq!(&[(10, "start"), (30, "tock"), (40, "stop")], events.as_slice());
+//! ```
+
+/// A cursor over the values of a sorted vector, created by `cursor_at`.
+#[derive(Debug, Clone)]
+pub struct Cursor<'a, T> {
+    values: &'a [T],
+    index: usize,
+}
+
+impl<'a, T> Cursor<'a, T> {
+    #[doc(hidden)]
+    pub fn new(values: &'a [T], index: usize) -> Self {
+        Cursor { values, index }
+    }
+
+    /// Returns the index of the current value, or the length of the collection when the
+    /// cursor is at its end.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the current value without moving the cursor.
+    pub fn peek(&self) -> Option<&'a T> {
+        self.values.get(self.index)
+    }
+
+    /// Returns the value before the cursor without moving it.
+    pub fn peek_prev(&self) -> Option<&'a T> {
+        self.index.checked_sub(1).map(|idx| &self.values[idx])
+    }
+
+    /// Returns the current value and moves the cursor past it.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<&'a T> {
+        let val = self.peek()?;
+        self.index += 1;
+        Some(val)
+    }
+
+    /// Moves the cursor back over the value before it and returns that value, which becomes
+    /// the current value.
+    pub fn prev(&mut self) -> Option<&'a T> {
+        let val = self.peek_prev()?;
+        self.index -= 1;
+        Some(val)
+    }
+}
+
+/// Operations on a sorted vector that a [`CursorMut`] needs. Implemented by all generated
+/// structs.
+///
+/// [`CursorMut`]: struct.CursorMut.html
+#[doc(hidden)]
+pub trait CursorTarget {
+    type Value;
+
+    fn values(&self) -> &[Self::Value];
+
+    fn remove_at(&mut self, idx: usize) -> Self::Value;
+
+    /// Inserts a value at the given index if its key fits between its neighbours there.
+    /// Returns whether it was inserted at that index, rather than routed elsewhere.
+    fn try_insert_at(&mut self, idx: usize, val: Self::Value) -> Result<bool, Self::Value>;
+}
+
+/// A cursor over the values of a sorted vector that can remove and insert values, created by
+/// `cursor_mut_at`. Insertions are checked to keep the collection sorted.
+#[derive(Debug)]
+pub struct CursorMut<'a, C> {
+    collection: &'a mut C,
+    index: usize,
+}
+
+impl<'a, C: CursorTarget> CursorMut<'a, C> {
+    #[doc(hidden)]
+    pub fn new(collection: &'a mut C, index: usize) -> Self {
+        CursorMut { collection, index }
+    }
+
+    /// Returns the index of the current value, or the length of the collection when the
+    /// cursor is at its end.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the current value without moving the cursor.
+    pub fn peek(&self) -> Option<&C::Value> {
+        self.collection.values().get(self.index)
+    }
+
+    /// Returns the value before the cursor without moving it.
+    pub fn peek_prev(&self) -> Option<&C::Value> {
+        let idx = self.index.checked_sub(1)?;
+        Some(&self.collection.values()[idx])
+    }
+
+    /// Returns the current value and moves the cursor past it.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<&C::Value> {
+        let val = self.collection.values().get(self.index)?;
+        self.index += 1;
+        Some(val)
+    }
+
+    /// Moves the cursor back over the value before it and returns that value, which becomes
+    /// the current value.
+    pub fn prev(&mut self) -> Option<&C::Value> {
+        self.index = self.index.checked_sub(1)?;
+        Some(&self.collection.values()[self.index])
+    }
+
+    /// Removes and returns the current value. The value after it becomes the current value.
+    pub fn remove_current(&mut self) -> Option<C::Value> {
+        if self.index < self.collection.values().len() {
+            Some(self.collection.remove_at(self.index))
+        } else {
+            None
+        }
+    }
+
+    /// Inserts a value before the cursor, so that the current value does not change. Returns
+    /// the value as an error when its key does not fit between the values around the cursor.
+    pub fn insert_before(&mut self, val: C::Value) -> Result<(), C::Value> {
+        if self.collection.try_insert_at(self.index, val)? {
+            self.index += 1;
+        }
+        Ok(())
+    }
+
+    /// Inserts a value after the cursor, so that it becomes the current value. Returns the
+    /// value as an error when its key does not fit between the values around the cursor.
+    pub fn insert_after(&mut self, val: C::Value) -> Result<(), C::Value> {
+        self.collection.try_insert_at(self.index, val).map(|_| ())
+    }
+}
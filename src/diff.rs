@@ -0,0 +1,70 @@
+//! Changes between two versions of a sorted vector, as produced by their `diff` and `diff_by`
+//! methods and replayed by `apply_patch_by` and `apply_patch`.
+//!
+//! ```rust
+//! use sortedvec::diff::Change;
+//! use sortedvec::sortedvec;
+//!
+//! sortedvec! {
+//!     #[derive(Debug, Clone)]
+//!     struct Settings {
+//!         fn derive_key(setting: &(&'static str, u32)) -> &'static str { setting.0 }
+//!         options { partial_eq }
+//!     }
+//! }
+//!
+//! let old = Settings::from(vec![("retries", 3), ("timeout", 30), ("verbose", 0)]);
+//! let new = Settings::from(vec![("port", 8080), ("retries", 3), ("timeout", 60)]);
+//! let patch: Vec<Change<_>> = old.diff(&new).map(Change::cloned).collect();
+//!
+//! assert_eq!(
+//!     vec![
+//!         Change::Added(("port", 8080)),
+//!         Change::Changed(("timeout", 30), ("timeout", 60)),
+//!         Change::Removed(("verbose", 0)),
+//!     ],
+//!     patch
+//! );
+//!
+//! let mut synced = old.clone();
+//! synced.apply_patch(patch);
+//! assert_eq!(new, synced[..]);
+//! ```
+
+/// A change to a sorted vector, in terms of its values.
+///
+/// Changes are computed per key. When a key occurs exactly once in both versions and its values
+/// differ, a `Changed` event is produced. When a key occurs a different number of times, or
+/// multiple times with differing values, all its old values are removed and all its new
+/// values are added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Change<T> {
+    /// A value was added.
+    Added(T),
+    /// A value was removed.
+    Removed(T),
+    /// A value was replaced by another value with the same key. Holds the old and the new value.
+    Changed(T, T),
+}
+
+impl<T> Change<T> {
+    /// Returns the value that determines the key of the change. This is the new value for
+    /// `Changed`, which has the same key as the old value.
+    pub fn value(&self) -> &T {
+        match self {
+            Change::Added(val) | Change::Removed(val) | Change::Changed(_, val) => val,
+        }
+    }
+}
+
+impl<T: Clone> Change<&T> {
+    /// Maps a change on borrowed values to one on owned values by cloning them, so that it can
+    /// be applied to another collection.
+    pub fn cloned(self) -> Change<T> {
+        match self {
+            Change::Added(val) => Change::Added(val.clone()),
+            Change::Removed(val) => Change::Removed(val.clone()),
+            Change::Changed(old, new) => Change::Changed(old.clone(), new.clone()),
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Errors returned when building and validating sorted vectors.
+
+use std::error::Error;
+use std::fmt;
+
+/// The error returned by `try_from_sorted` when the given values are not sorted. It holds the
+/// values, so that they can be sorted after all using `From`.
+///
+/// ```rust
+/// use sortedvec::sortedvec;
+///
+/// sortedvec! {
+///     #[derive(Debug)]
+///     struct Numbers {
+///         fn derive_key(x: &u32) -> u32 { *x }
+///     }
+/// }
+///
+/// let err = Numbers::try_from_sorted(vec![1, 3, 2, 4]).unwrap_err();
+/// assert_eq!(2, err.index());
+///
+/// let numbers = Numbers::from(err.into_vec());
+/// assert_eq!(&[1, 2, 3, 4], &numbers[..]);
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct NotSorted<T> {
+    index: usize,
+    values: Vec<T>,
+}
+
+impl<T> NotSorted<T> {
+    #[doc(hidden)]
+    pub fn new(index: usize, values: Vec<T>) -> Self {
+        NotSorted { index, values }
+    }
+
+    /// Returns the index of the first value that is out of order with respect to the value
+    /// before it.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the values that were passed to `try_from_sorted`.
+    pub fn into_vec(self) -> Vec<T> {
+        self.values
+    }
+}
+
+impl<T> fmt::Debug for NotSorted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NotSorted")
+            .field("index", &self.index)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Display for NotSorted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "value at index {} is out of order", self.index)
+    }
+}
+
+impl<T> Error for NotSorted<T> {}
+
+/// The error returned by `validate` when a sorted vector does not uphold its invariants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvariantViolation {
+    /// The value at the given index is out of order with respect to the value before it, or has
+    /// the same key while the collection is `unique`.
+    NotSorted(usize),
+    /// Deriving the key of the value at the given index twice gave different keys.
+    NonDeterministicKey(usize),
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvariantViolation::NotSorted(index) => {
+                write!(f, "value at index {} is out of order", index)
+            }
+            InvariantViolation::NonDeterministicKey(index) => {
+                write!(f, "key of value at index {} is not deterministic", index)
+            }
+        }
+    }
+}
+
+impl Error for InvariantViolation {}
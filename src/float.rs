@@ -0,0 +1,152 @@
+//! Wrappers that give floating point numbers a total order, so that they can be used as keys
+//! of sorted vectors.
+//!
+//! Regular `f32`s and `f64`s only implement `PartialOrd`, because `NaN` is not comparable to
+//! anything. The wrappers in this module order values by the IEEE 754 `totalOrder` predicate,
+//! except for `NaN`s, which are all considered equal to each other and placed either before
+//! ([`NanFirst`]) or after ([`NanLast`]) all other values.
+//!
+//! ```rust
+//! use sortedvec::sortedvec;
+//! use sortedvec::float::NanLast;
+//!
+//! sortedvec! {
+//!     struct Calibration {
+//!         fn derive_key(x: &(f64, &'static str)) -> NanLast<f64> { NanLast(x.0) }
+//!     }
+//! }
+//!
+//! let table = Calibration::from(vec![(0.5, "low"), (2.5, "high"), (1.5, "mid")]);
+//!
+//! assert_eq!(Some(&(1.5, "mid")), table.find(&NanLast(1.5)));
+//! assert_eq!(Some(&(1.5, "mid")), table.find_within(&NanLast(1.45), 0.1));
+//! assert_eq!(None, table.find_within(&NanLast(1.0), 0.1));
+//! ```
+//!
+//! [`NanFirst`]: struct.NanFirst.html
+//! [`NanLast`]: struct.NanLast.html
+
+use std::cmp::Ordering;
+
+mod private {
+    pub trait Sealed {}
+
+    impl Sealed for f32 {}
+    impl Sealed for f64 {}
+}
+
+/// Floating point types that can be wrapped in [`NanFirst`] and [`NanLast`]. This trait is
+/// sealed and implemented for `f32` and `f64` only.
+///
+/// [`NanFirst`]: struct.NanFirst.html
+/// [`NanLast`]: struct.NanLast.html
+pub trait Float: Copy + PartialOrd + private::Sealed {
+    /// Returns `true` if this value is `NaN`.
+    fn is_nan_value(self) -> bool;
+
+    /// Compares two values using the IEEE 754 `totalOrder` predicate.
+    fn cmp_total(self, other: Self) -> Ordering;
+
+    /// Returns the absolute difference between two values.
+    fn abs_diff(self, other: Self) -> Self;
+}
+
+macro_rules! impl_float {
+    ($($t:ty),*) => {
+        $(
+            impl Float for $t {
+                #[inline]
+                fn is_nan_value(self) -> bool {
+                    self.is_nan()
+                }
+
+                #[inline]
+                fn cmp_total(self, other: Self) -> Ordering {
+                    self.total_cmp(&other)
+                }
+
+                #[inline]
+                fn abs_diff(self, other: Self) -> Self {
+                    (self - other).abs()
+                }
+            }
+        )*
+    }
+}
+
+impl_float!(f32, f64);
+
+/// Keys that have a notion of distance, which enables approximate lookups through
+/// the `find_within` method of generated sorted vectors.
+pub trait FloatKey: Ord {
+    /// The type of the distance between two keys.
+    type Float: Float;
+
+    /// Returns the absolute distance between two keys. This is `NaN` when either key is `NaN`.
+    fn distance(&self, other: &Self) -> Self::Float;
+}
+
+macro_rules! nan_wrapper {
+    ($(#[$attr:meta])* $name:ident, $nan_ordering:expr) => {
+        $(#[$attr])*
+        #[derive(Debug, Clone, Copy, Default)]
+        pub struct $name<F>(pub F);
+
+        impl<F: Float> Ord for $name<F> {
+            fn cmp(&self, other: &Self) -> Ordering {
+                match (self.0.is_nan_value(), other.0.is_nan_value()) {
+                    (true, true) => Ordering::Equal,
+                    (true, false) => $nan_ordering,
+                    (false, true) => $nan_ordering.reverse(),
+                    (false, false) => self.0.cmp_total(other.0),
+                }
+            }
+        }
+
+        impl<F: Float> PartialOrd for $name<F> {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl<F: Float> PartialEq for $name<F> {
+            fn eq(&self, other: &Self) -> bool {
+                self.cmp(other) == Ordering::Equal
+            }
+        }
+
+        impl<F: Float> Eq for $name<F> {}
+
+        impl<F: Float> From<F> for $name<F> {
+            fn from(f: F) -> Self {
+                $name(f)
+            }
+        }
+
+        impl<F: Float> FloatKey for $name<F> {
+            type Float = F;
+
+            fn distance(&self, other: &Self) -> F {
+                self.0.abs_diff(other.0)
+            }
+        }
+    }
+}
+
+nan_wrapper!(
+    /// Totally ordered floating point value that places `NaN`s before all other values.
+    ///
+    /// Non-`NaN` values are ordered by the IEEE 754 `totalOrder` predicate, which means that
+    /// `-0.0` is considered smaller than `+0.0`. All `NaN`s are equal to each other.
+    NanFirst,
+    Ordering::Less
+);
+
+nan_wrapper!(
+    /// Totally ordered floating point value that places `NaN`s after all other values.
+    ///
+    /// Non-`NaN` values are ordered by the IEEE 754 `totalOrder` predicate, which means that
+    /// `-0.0` is considered smaller than `+0.0`. All `NaN`s are equal to each other.
+    NanLast,
+    Ordering::Greater
+);
@@ -0,0 +1,249 @@
+//! Immutable sorted vectors for read-mostly tables. Freezing a sorted vector moves its values
+//! into the Eytzinger layout, the order of a breadth-first traversal of a complete binary search
+//! tree. Searching this layout touches memory in a predictable pattern, so that lookups can
+//! prefetch the values they will compare against several levels down.
+//!
+//! ```rust
+//! use sortedvec::sortedvec;
+//!
+//! sortedvec! {
+//!     struct Ports {
+//!         fn derive_key(entry: &(u16, &'static str)) -> u16 { entry.0 }
+//!     }
+//! }
+//!
+//! let ports = Ports::from(vec![(443, "https"), (22, "ssh"), (80, "http"), (53, "dns")]);
+//! let frozen = ports.freeze();
+//!
+//! assert_eq!(Some(&(80, "http")), frozen.find(&80));
+//! assert!(!frozen.contains(&8080));
+//! assert_eq!(vec![22, 53, 80, 443], frozen.iter().map(|entry| entry.0).collect::<Vec<_>>());
+//!
+//! let mut ports = frozen.thaw();
+//! ports.insert((8080, "http-alt"));
+//! assert_eq!(5, ports.len());
+//! ```
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::iter::FusedIterator;
+
+use crate::merge;
+
+/// Access to the values of a sorted vector that [`Frozen`] needs. Implemented by all generated
+/// structs.
+///
+/// [`Frozen`]: struct.Frozen.html
+#[doc(hidden)]
+pub trait Freeze {
+    type Value;
+
+    fn values(&self) -> &[Self::Value];
+
+    /// Gives mutable access to the values, bypassing the invariants of the collection.
+    ///
+    /// # Safety
+    ///
+    /// Before the collection is used through any method other than `values` and `thawed`, its
+    /// values must be put back in the order they had when this was called, followed by a call
+    /// to `thawed`. Lookups on a collection whose values are out of order may cause undefined
+    /// behavior, so safe code can't call it:
+    ///
+    /// ```compile_fail,E0133
+    /// use sortedvec::frozen::Freeze;
+    /// use sortedvec::sortedvec;
+    ///
+    /// sortedvec! {
+    ///     struct Numbers {
+    ///         fn derive_key(x: &u32) -> u32 { *x }
+    ///     }
+    /// }
+    ///
+    /// let mut numbers = Numbers::from(vec![1, 2, 3]);
+    /// numbers.values_mut().reverse();
+    /// ```
+    unsafe fn values_mut(&mut self) -> &mut Vec<Self::Value>;
+
+    /// Restores the secondary indexes after the values were put back in key order.
+    fn thawed(&mut self);
+}
+
+/// Comparison of the values of a sorted vector with a key, in the order of the collection.
+/// Implemented by all generated structs for the same key types as `SortedCollection`.
+#[doc(hidden)]
+pub trait FrozenLookup<Key: ?Sized>: Freeze {
+    fn cmp_key(&self, val: &Self::Value, key: &Key) -> Ordering;
+}
+
+/// An immutable sorted vector that stores its values in the Eytzinger layout, created by the
+/// `freeze` method of a sorted vector and turned back into it by `thaw`.
+///
+/// Frozen collections support lookups by the same key types as `SortedCollection` and
+/// iteration in key order. Values without a key, kept by collections with an `#[optional]`
+/// key derivation function, are not part of the layout, but are restored when thawing.
+#[derive(Clone)]
+pub struct Frozen<C> {
+    collection: C,
+}
+
+impl<C: Freeze> Frozen<C> {
+    #[doc(hidden)]
+    pub fn new(mut collection: C) -> Self {
+        // SAFETY: the collection is only accessed through `values` while it is frozen, and
+        // `thaw` restores the key order before handing it back.
+        let values = unsafe { collection.values_mut() };
+        let order = eytzinger_order(values.len());
+        let mut sorted: Vec<Option<C::Value>> = values.drain(..).map(Some).collect();
+        values.extend(order.into_iter().map(|idx| sorted[idx].take().unwrap()));
+        Frozen { collection }
+    }
+
+    /// Turns the frozen collection back into a sorted vector, in linear time.
+    pub fn thaw(mut self) -> C {
+        // SAFETY: the values are put back in key order and `thawed` is called below, before
+        // the collection is returned.
+        let values = unsafe { self.collection.values_mut() };
+        let order = eytzinger_order(values.len());
+        let mut sorted: Vec<Option<C::Value>> = values.iter().map(|_| None).collect();
+        for (idx, val) in order.into_iter().zip(values.drain(..)) {
+            sorted[idx] = Some(val);
+        }
+        values.extend(sorted.into_iter().map(Option::unwrap));
+        self.collection.thawed();
+        self.collection
+    }
+
+    /// Returns the number of values in the collection.
+    pub fn len(&self) -> usize {
+        self.collection.values().len()
+    }
+
+    /// Returns `true` if the collection contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns an iterator over the values of the collection, in key order.
+    pub fn iter(&self) -> Iter<'_, C::Value> {
+        let layout = self.collection.values();
+        Iter {
+            layout,
+            node: leftmost(1, layout.len()),
+            remaining: layout.len(),
+        }
+    }
+
+    /// Tries to find an element in the collection with the given key. When several elements
+    /// have that key, the first of them in key order is returned. This has logarithmic worst
+    /// case time complexity.
+    pub fn find<Key: ?Sized>(&self, key: &Key) -> Option<&C::Value>
+    where
+        C: FrozenLookup<Key>,
+    {
+        let layout = self.collection.values();
+        // Descend the implicit tree, where the children of node `k` are `2k` and `2k + 1` and
+        // node `k` is stored at index `k - 1`.
+        let mut node = 1;
+        while node <= layout.len() {
+            merge::prefetch(layout, 16 * node - 1);
+            let less = self.collection.cmp_key(&layout[node - 1], key) == Ordering::Less;
+            node = 2 * node + less as usize;
+        }
+        // Undo the steps to the right after the last step to the left, which lead to the first
+        // value that is not less than the key.
+        node >>= node.trailing_ones() + 1;
+        let val = layout.get(node.checked_sub(1)?)?;
+        match self.collection.cmp_key(val, key) {
+            Ordering::Equal => Some(val),
+            _ => None,
+        }
+    }
+
+    /// Checks whether there is a value with that key in the collection.
+    pub fn contains<Key: ?Sized>(&self, key: &Key) -> bool
+    where
+        C: FrozenLookup<Key>,
+    {
+        self.find(key).is_some()
+    }
+}
+
+impl<'a, C: Freeze> IntoIterator for &'a Frozen<C> {
+    type Item = &'a C::Value;
+    type IntoIter = Iter<'a, C::Value>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Formats the values in key order rather than in the layout they are stored in.
+impl<C: Freeze> fmt::Debug for Frozen<C>
+where
+    C::Value: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// An iterator over the values of a [`Frozen`] collection, in key order.
+///
+/// [`Frozen`]: struct.Frozen.html
+#[derive(Debug, Clone)]
+pub struct Iter<'a, T> {
+    layout: &'a [T],
+    node: usize,
+    remaining: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let val = self.layout.get(self.node.checked_sub(1)?)?;
+        self.node = if 2 * self.node < self.layout.len() {
+            leftmost(2 * self.node + 1, self.layout.len())
+        } else {
+            // Climb up past the nodes of which this is the right subtree.
+            self.node >> (self.node.trailing_ones() + 1)
+        };
+        self.remaining -= 1;
+        Some(val)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+impl<'a, T> FusedIterator for Iter<'a, T> {}
+
+/// Returns the first node in key order of the subtree rooted at `node`, which is `0` for an
+/// empty tree.
+fn leftmost(mut node: usize, len: usize) -> usize {
+    if node > len {
+        return 0;
+    }
+    while 2 * node <= len {
+        node *= 2;
+    }
+    node
+}
+
+/// Returns the index in key order of the value at every position of the Eytzinger layout.
+fn eytzinger_order(len: usize) -> Vec<usize> {
+    let mut order = vec![0; len];
+    let mut node = leftmost(1, len);
+    for idx in 0..len {
+        order[node - 1] = idx;
+        node = if 2 * node < len {
+            leftmost(2 * node + 1, len)
+        } else {
+            node >> (node.trailing_ones() + 1)
+        };
+    }
+    order
+}
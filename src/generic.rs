@@ -0,0 +1,81 @@
+//! A single generic sorted vector type, parameterized by a zero-sized key extractor type.
+//!
+//! Unlike the structs generated by `sortedvec!`, every `SortedVec<X>` is the same generic type,
+//! so functions and trait implementations can be written once for all of them. The methods
+//! and trait implementations are the same as those of a struct generated by `sortedvec!`
+//! with only the `partial_eq` option, and the `stable` option when `X::STABLE` is `true`.
+//!
+//! ```rust
+//! use sortedvec::{KeyExtractor, SortedVec};
+//!
+//! struct ById;
+//!
+//! impl KeyExtractor for ById {
+//!     type Value = (u64, &'static str);
+//!     type Key = u64;
+//!
+//!     fn key(v: &Self::Value) -> u64 {
+//!         v.0
+//!     }
+//! }
+//!
+//! fn describe<X: KeyExtractor>(sorted: &SortedVec<X>) -> String {
+//!     format!("{} values", sorted.len())
+//! }
+//!
+//! let users: SortedVec<ById> = vec![(2, "bob"), (1, "alice")].into();
+//!
+//! assert_eq!(Some(&(2, "bob")), users.find(&2));
+//! assert_eq!("2 values", describe(&users));
+//! ```
+//!
+//! Since the key is returned by value from a function without access to the lifetime of the
+//! value, it cannot borrow from the value. Use one of the macros for borrowed keys.
+
+use std::fmt;
+
+/// Describes how the keys of a [`SortedVec`] are derived from its values. Implementors are
+/// typically zero-sized types that are never instantiated.
+///
+/// [`SortedVec`]: struct.SortedVec.html
+pub trait KeyExtractor {
+    /// The type of the values stored in the sorted vector.
+    type Value;
+
+    /// The type of the keys the values are sorted on.
+    type Key: Ord;
+
+    /// Whether values with equal keys keep their insertion order, like the `stable` option of
+    /// `sortedvec!`. Defaults to `false`.
+    const STABLE: bool = false;
+
+    /// Derives the key of a value.
+    fn key(v: &Self::Value) -> Self::Key;
+}
+
+/// Sorted vector of `X::Value`s that provides quick access through `X::Key`s.
+pub struct SortedVec<X: KeyExtractor>(Vec<X::Value>);
+
+impl<X: KeyExtractor> Clone for SortedVec<X>
+where
+    X::Value: Clone,
+{
+    fn clone(&self) -> Self {
+        SortedVec(self.0.clone())
+    }
+}
+
+impl<X: KeyExtractor> fmt::Debug for SortedVec<X>
+where
+    X::Value: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SortedVec").field(&self.0).finish()
+    }
+}
+
+crate::sortedvec! {
+    @newtype SortedVec [X: KeyExtractor,] [X,] [] scalar
+    fn derive_key(val: &X::Value) -> X::Key [] [X::key]
+    [[] { X::STABLE } false [partial_eq] binary]
+}
@@ -0,0 +1,79 @@
+//! Keys that support interpolation search, which is selected by the `search = interpolation`
+//! option of `sortedvec!`.
+//!
+//! Interpolation search estimates the position of a key from its distance to the keys at both
+//! ends of the search window, like looking up a word in a dictionary. For keys that are spread
+//! evenly, like dense ids or timestamps, it finds values in `O(log(log(n)))` comparisons. When
+//! an estimate does not halve the search window, the next step halves it instead, so that the
+//! worst case stays `O(log(n))`.
+//!
+//! ```rust
+//! use sortedvec::sortedvec;
+//!
+//! sortedvec! {
+//!     struct Readings {
+//!         fn derive_key(reading: &(u64, f32)) -> u64 { reading.0 }
+//!         options { search = interpolation }
+//!     }
+//! }
+//!
+//! let readings: Readings = (0..1000).map(|t| (1_600_000_000 + 60 * t, 0.5)).collect();
+//!
+//! assert_eq!(Ok(500), readings.position(&1_600_030_000));
+//! assert_eq!(Err(501), readings.position(&1_600_030_001));
+//! ```
+
+/// A key that can be mapped to an integer in an order preserving way, so that it can be
+/// searched for by interpolation.
+///
+/// The mapping must be monotonic: when `a < b`, then `a.to_u64() <= b.to_u64()`. Keys that map
+/// to the same integer are still found, but slow down the search. The closer the distribution
+/// of the integers is to uniform, the faster interpolation search is.
+pub trait InterpolationKey {
+    /// Maps the key to an integer.
+    fn to_u64(&self) -> u64;
+}
+
+macro_rules! impl_unsigned {
+    ($($ty:ty),*) => {
+        $(
+            impl InterpolationKey for $ty {
+                #[inline]
+                fn to_u64(&self) -> u64 {
+                    *self as u64
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_signed {
+    ($($ty:ty),*) => {
+        $(
+            impl InterpolationKey for $ty {
+                #[inline]
+                fn to_u64(&self) -> u64 {
+                    // Flipping the sign bit maps the minimum to zero and preserves the order.
+                    (*self as i64 as u64) ^ (1 << 63)
+                }
+            }
+        )*
+    };
+}
+
+impl_unsigned!(u8, u16, u32, u64, usize);
+impl_signed!(i8, i16, i32, i64, isize);
+
+impl InterpolationKey for char {
+    #[inline]
+    fn to_u64(&self) -> u64 {
+        *self as u64
+    }
+}
+
+impl<T: InterpolationKey + ?Sized> InterpolationKey for &T {
+    #[inline]
+    fn to_u64(&self) -> u64 {
+        (**self).to_u64()
+    }
+}
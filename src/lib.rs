@@ -35,6 +35,72 @@ extern crate quickcheck_macros;
 /// An example of a data structure defined using the `sortedvec!` macro.
 pub mod example;
 
+/// Totally ordered floating point wrappers that can be used as keys.
+pub mod float;
+
+/// A trait implemented by all sorted vectors.
+pub mod collection;
+
+/// A generic sorted vector type, as an alternative to the macros.
+pub mod generic;
+
+/// Changes between two versions of a sorted vector.
+pub mod diff;
+
+/// Errors returned when building and validating sorted vectors.
+pub mod error;
+
+/// Cursors that walk and edit sorted vectors from a position found by key.
+pub mod cursor;
+
+/// Immutable sorted vectors that store their values in a layout optimized for lookups.
+pub mod frozen;
+
+/// Keys that support interpolation search.
+pub mod interpolation;
+
+/// Merge algorithms used by the generated set operations and joins.
+#[doc(hidden)]
+pub mod merge;
+
+pub use collection::SortedCollection;
+pub use error::{InvariantViolation, NotSorted};
+pub use generic::{KeyExtractor, SortedVec};
+pub use interpolation::InterpolationKey;
+
+/// Derive macro that implements a sorted vector on a newtype `struct Name(Vec<T>)`, as an
+/// alternative to the `sortedvec!` macro. Requires the `derive` feature.
+#[cfg(feature = "derive")]
+pub use sortedvec_derive::SortedVec;
+
+/// Whether generated structs check their invariants in builds without debug assertions too, and
+/// check the whole collection after operations on a single value. Enabled by the `validate`
+/// feature.
+#[doc(hidden)]
+pub const VALIDATE: bool = cfg!(feature = "validate");
+
+/// Helper trait that lets generated methods put bounds on the key type of a sorted vector,
+/// which cannot be named directly in `where` clauses when it contains elided lifetimes.
+/// It is implemented for key derivation functions `fn(&V) -> K` and `fn(&C, &V) -> K`.
+#[doc(hidden)]
+pub trait DeriveKey<C, V, K> {
+    fn derive(self, context: &C, val: &V) -> K;
+}
+
+impl<C, V, K> DeriveKey<C, V, K> for fn(&V) -> K {
+    #[inline]
+    fn derive(self, _context: &C, val: &V) -> K {
+        self(val)
+    }
+}
+
+impl<C, V, K> DeriveKey<C, V, K> for fn(&C, &V) -> K {
+    #[inline]
+    fn derive(self, context: &C, val: &V) -> K {
+        self(context, val)
+    }
+}
+
 /// A macro that defines a sorted vector data structure.
 ///
 /// The generated struct is specific to the given keys and value types. To create the struct,
@@ -47,13 +113,22 @@ pub mod example;
 /// It matches the following input:
 /// ```text
 /// $(#[$attr:meta])*
-/// $v:vis struct $name:ident {
+/// $v:vis struct $name:ident
+///     $(< $($lt:lifetime),* $(,)? $($tp:ident $(: $tb:path)?),* $(,)? >)?
+///     $(where $($wty:ty : $wb:path),+ $(,)?)?
+/// {
 ///     fn derive_key($i:ident : & $val:ty) -> $key:ty {
 ///         $keyexpr:expr
 ///     } $(,)?
+///     $(options { $($option),* $(,)? })?
 /// }
 /// ```
 ///
+/// The struct may have lifetime and type parameters, which are propagated to all generated
+/// implementations. A type parameter can have a single bound inline, additional bounds
+/// go into the `where` clause, one per predicate. Type parameters named `Iter`, `Other` or
+/// `QueryKey` and the lifetime `'sorted` are reserved.
+///
 /// To get an overview of the exposed methods on the generated structure, see the documentation
 /// of the example module.
 ///
@@ -81,491 +156,4275 @@ pub mod example;
 ///
 /// let sv = ExampleSortedVec::default();
 /// ```
+///
+/// # Options
+///
+/// The behavior of the generated struct can be tuned by ending its body with an `options`
+/// block containing any of the following, separated by commas:
+/// - `unique`: at most one value is kept per key. Inserting a value replaces the value with
+///   the same key, and when the struct is built from a `Vec`, an iterator or extended, the last
+///   value for every key wins. `unique = last` is the same, while with `unique = first` the
+///   value already present or the first one for every key wins instead,
+/// - `merge = $path:path`: like `unique`, but duplicates are merged into the value already
+///   present or the first one for every key by a function `fn(&mut T, T)`, in order. It must
+///   not change the key of the value it merges into. Merges that leave it out of order panic,
+/// - `stable`: values with equal keys keep their insertion order. New values are inserted after
+///   existing values with the same key and `position` and `find` return the first of them,
+/// - `order = ascending` or `order = descending`: the direction in which keys are sorted.
+///   Ascending is the default,
+/// - `key_fn = $path:path`: derive keys through an existing function instead of an inline
+///   expression. `derive_key` must then end in a semicolon rather than a body,
+/// - `search = binary` or `search = branchless`: the algorithm used by `position` and the
+///   methods built on it. The default `binary` search is fastest for tables that fit in the
+///   cache. The `branchless` search avoids branch mispredictions and prefetches the values it
+///   may compare against next, which pays off for tables larger than the cache. It returns the
+///   first of several values with equal keys. `search = interpolation` estimates the positions
+///   of keys from their values, which is fastest for evenly spread keys like dense ids or
+///   timestamps. It requires keys that implement [`InterpolationKey`] and also returns the first
+///   of several values with equal keys,
+/// - `partial_eq`: implement `PartialEq<[T]>` and `PartialEq<Vec<T>>` for the struct and
+///   `PartialEq<Struct>` for `Vec<T>`, so that it can be compared to expected values directly.
+///   Requires `T: PartialEq`,
+/// - `debug_map`: implement `Debug` by formatting the struct as a map of keys to values.
+///   Requires `T: Debug` and `K: Debug`. `Debug` can then not be derived as well.
+///
+/// [`InterpolationKey`]: trait.InterpolationKey.html
+///
+/// ```rust
+/// use sortedvec::sortedvec;
+///
+/// fn score(entry: &(&'static str, u32)) -> u32 {
+///     entry.1
+/// }
+///
+/// sortedvec! {
+///     pub struct Leaderboard {
+///         fn derive_key(entry: &(&'static str, u32)) -> u32;
+///         options { unique, order = descending, key_fn = score }
+///     }
+/// }
+///
+/// let board: Leaderboard = vec![("ann", 10), ("bob", 30), ("cid", 20), ("dan", 30)].into();
+///
+/// assert_eq!(&[("dan", 30), ("cid", 20), ("ann", 10)], &board[..]);
+/// ```
+///
+/// Regardless of options, the generated struct can be indexed by key using `table[&key]`,
+/// which panics when there is no value with that key, as well as by position and range.
+/// Traits like `PartialEq`, `Eq`, `Hash`, `PartialOrd` and `Ord` can be derived, and then
+/// compare the sorted values.
+///
+/// # Secondary indexes
+///
+/// Values can be looked up by additional keys by adding functions annotated with
+/// `#[index(find = $find:ident, range = $range:ident)]` after `derive_key`. Values are
+/// stored once, ordered by the primary key derived by `derive_key`. For every secondary index,
+/// the generated struct keeps a permutation of value indices that is sorted on that key
+/// and that is updated by all mutating methods. The given names are used for the generated
+/// lookup methods:
+/// - `$find(&self, key: &K) -> Option<&T>` finds a value by its secondary key,
+/// - `$range(&self, start: Bound<&K>, end: Bound<&K>) -> impl Iterator<Item = &T>` iterates
+///   over the values with secondary keys between the given bounds, in order of that key.
+///
+/// Secondary indexes make every insertion and removal `O(n)` per index. They cannot be named
+/// `inner` and are not supported in combination with a key derivation context.
+///
+/// ```rust
+/// use sortedvec::sortedvec;
+/// use std::ops::Bound::{Included, Unbounded};
+///
+/// pub struct User {
+///     id: u64,
+///     name: String,
+/// }
+///
+/// sortedvec! {
+///     pub struct Users {
+///         fn derive_key(u: &User) -> u64 { u.id }
+///         #[index(find = find_by_name, range = range_by_name)]
+///         fn name_key(u: &User) -> &str { &u.name }
+///     }
+/// }
+///
+/// let mut users = Users::default();
+/// users.insert(User { id: 2, name: "alice".to_owned() });
+/// users.insert(User { id: 1, name: "bob".to_owned() });
+///
+/// assert_eq!(Some(2), users.find_by_name(&"alice").map(|u| u.id));
+/// assert_eq!(1, users.range_by_name(Included(&"b"), Unbounded).count());
+/// ```
+///
+/// # Optional keys
+///
+/// When not all values have a key, the key derivation function can be annotated with
+/// `#[optional]` and return an `Option<K>`. Values for which no key is derived are kept in
+/// a separate, unsorted bucket that can be inspected using `unkeyed` and emptied using
+/// `take_unkeyed`. Lookups only consider keyed values and the struct dereferences to the keyed
+/// values only, but conversion into a `Vec` or an iterator yields all values, with the
+/// unkeyed values last.
+///
+/// ```rust
+/// use sortedvec::sortedvec;
+///
+/// sortedvec! {
+///     pub struct Ports {
+///         #[optional]
+///         fn derive_key(x: &String) -> Option<u16> { x.parse().ok() }
+///     }
+/// }
+///
+/// let ports: Ports = vec!["80".to_owned(), "http".to_owned(), "22".to_owned()].into();
+///
+/// assert_eq!(Some(&"22".to_owned()), ports.find(&22));
+/// assert_eq!(&["http".to_owned()], ports.unkeyed());
+/// ```
+///
+/// # Key derivation context
+///
+/// When the order of the collection depends on runtime state, the key derivation function can
+/// take a reference to a context value as its first argument:
+/// ```text
+/// $(#[$attr:meta])*
+/// $v:vis struct $name:ident $(<..>)? $(where ..)? {
+///     fn derive_key($c:ident : & $ctx:ty, $i:ident : & $val:ty) -> $key:ty {
+///         $keyexpr:expr
+///     } $(,)?
+/// }
+/// ```
+///
+/// The context is stored in the generated struct and must implement `Clone`. Since there is no
+/// sensible default context, such structs do not implement `Default`, `From<Vec<_>>` and
+/// `FromIterator`. Instead, they are created using `with_context` and `from_vec_with_context`.
+/// Keys derived from a context cannot borrow from the value or the context.
+///
+/// Operations on two collections derive all keys using the context of `self`. The consuming set
+/// operations like `into_union` sort the values of `other` again when they are out of order under
+/// that context. The other set operations and `diff_by` expect them to be in order already,
+/// which is checked in debug builds only.
+///
+/// ```rust
+/// use sortedvec::sortedvec;
+///
+/// #[derive(Clone)]
+/// pub struct Column(usize);
+///
+/// sortedvec! {
+///     pub struct Table {
+///         fn derive_key(column: &Column, row: &[u32; 3]) -> u32 { row[column.0] }
+///     }
+/// }
+///
+/// let rows = vec![[1, 9, 5], [2, 8, 4], [3, 7, 6]];
+/// let by_second = Table::from_vec_with_context(rows.clone(), Column(1));
+/// let by_third = Table::from_vec_with_context(rows, Column(2));
+///
+/// assert_eq!(Some(&[2, 8, 4]), by_second.find(&8));
+/// assert_eq!(&[2, 8, 4], &by_third[0]);
+/// ```
+///
+/// # Existing newtypes
+///
+/// Instead of defining a new struct, the macro can implement a sorted vector on an existing
+/// newtype `struct Name(Vec<T>)` in the same module, which must not implement `Default` itself.
+/// This is what the `SortedVec` derive macro expands to. It matches the following input:
+/// ```text
+/// impl $name:ident $(<..>)? $(where ..)? {
+///     fn derive_key($i:ident : & $val:ty) -> $key:ty {
+///         $keyexpr:expr
+///     } $(,)?
+///     $(options { $($option),* $(,)? })?
+/// }
+/// ```
+///
+/// Secondary indexes, optional keys and key derivation contexts are not supported on newtypes.
+///
+/// ```rust
+/// use sortedvec::sortedvec;
+///
+/// #[derive(Debug)]
+/// pub struct Releases(Vec<(u32, &'static str)>);
+///
+/// sortedvec! {
+///     impl Releases {
+///         fn derive_key(release: &(u32, &'static str)) -> u32 { release.0 }
+///         options { unique }
+///     }
+/// }
+///
+/// let releases = Releases::from(vec![(2, "beta"), (1, "alpha"), (2, "rc")]);
+///
+/// assert_eq!(Some(&(2, "rc")), releases.find(&2));
+/// ```
 #[macro_export]
 macro_rules! sortedvec {
 (
     $(#[$attr:meta])*
-    $v:vis struct $name:ident {
-        fn derive_key($i:ident : & $val:ty) -> $key:ty {
-            $keyexpr:expr
-        } $(,)?
+    $v:vis struct $name:ident
+        $(< $($lt:lifetime),* $(,)? $($tp:ident $(: $tb:path)?),* $(,)? >)?
+        $(where $($wty:ty : $wb:path),+ $(,)?)?
+    {
+        $($body:tt)*
+    }
+) => {
+        $crate::sortedvec! {
+            @key [
+                [$(#[$attr])*] $v $name
+                [$($($lt,)* $($tp $(: $tb)?,)*)?] [$($($lt,)* $($tp,)*)?] [$($($wty: $wb,)+)?]
+                scalar
+            ]
+            $($body)*
+        }
+    };
+(
+    impl $name:ident
+        $(< $($lt:lifetime),* $(,)? $($tp:ident $(: $tb:path)?),* $(,)? >)?
+        $(where $($wty:ty : $wb:path),+ $(,)?)?
+    {
+        $($body:tt)*
     }
+) => {
+        $crate::sortedvec! {
+            @key [
+                newtype $name
+                [$($($lt,)* $($tp $(: $tb)?,)*)?] [$($($lt,)* $($tp,)*)?] [$($($wty: $wb,)+)?]
+                scalar
+            ]
+            $($body)*
+        }
+    };
+(
+    @key $front:tt
+    #[optional]
+    fn derive_key($i:ident : & $val:ty) -> Option<$key:ty> { $keyexpr:expr } $($rest:tt)*
+) => {
+        $crate::sortedvec! {
+            @indexes $front [optional fn derive_key($i: &$val) -> Option<$key> [{ $keyexpr }]] []
+            $($rest)*
+        }
+    };
+(
+    @key $front:tt
+    #[optional]
+    fn derive_key($i:ident : & $val:ty) -> Option<$key:ty>; $($rest:tt)*
+) => {
+        $crate::sortedvec! {
+            @indexes $front [optional fn derive_key($i: &$val) -> Option<$key> []] []
+            $($rest)*
+        }
+    };
+(
+    @key $front:tt
+    fn derive_key($c:ident : & $ctx:ty, $i:ident : & $val:ty) -> $key:ty { $keyexpr:expr }
+    $($rest:tt)*
+) => {
+        $crate::sortedvec! {
+            @indexes $front [context fn derive_key($c: &$ctx, $i: &$val) -> $key [{ $keyexpr }]] []
+            $($rest)*
+        }
+    };
+(
+    @key $front:tt
+    fn derive_key($c:ident : & $ctx:ty, $i:ident : & $val:ty) -> $key:ty; $($rest:tt)*
+) => {
+        $crate::sortedvec! {
+            @indexes $front [context fn derive_key($c: &$ctx, $i: &$val) -> $key []] []
+            $($rest)*
+        }
+    };
+(
+    @key $front:tt
+    fn derive_key($i:ident : & $val:ty) -> $key:ty { $keyexpr:expr } $($rest:tt)*
+) => {
+        $crate::sortedvec! {
+            @indexes $front [plain fn derive_key($i: &$val) -> $key [{ $keyexpr }]] []
+            $($rest)*
+        }
+    };
+(
+    @key $front:tt
+    fn derive_key($i:ident : & $val:ty) -> $key:ty; $($rest:tt)*
+) => {
+        $crate::sortedvec! {
+            @indexes $front [plain fn derive_key($i: &$val) -> $key []] []
+            $($rest)*
+        }
+    };
+(
+    @indexes $front:tt $key:tt [$($idx:tt)*] , $($rest:tt)*
+) => {
+        $crate::sortedvec! { @indexes $front $key [$($idx)*] $($rest)* }
+    };
+(
+    @indexes $front:tt $key:tt [$($idx:tt)*]
+    #[index(find = $find:ident, range = $range:ident)]
+    fn $iname:ident($ii:ident : & $ival:ty) -> $ikey:ty { $iexpr:expr } $($rest:tt)*
+) => {
+        $crate::sortedvec! {
+            @indexes $front $key
+            [$($idx)* [$find $range] fn $iname($ii: &$ival) -> $ikey { $iexpr }]
+            $($rest)*
+        }
+    };
+(
+    @indexes $front:tt $key:tt $idx:tt options { $($opts:tt)* } $(,)?
+) => {
+        $crate::sortedvec! { @options $front $key $idx [[] false false [] [] binary] $($opts)* }
+    };
+(
+    @indexes $front:tt $key:tt $idx:tt
+) => {
+        $crate::sortedvec! { @emit $front $key $idx [[] false false [] [] binary] }
+    };
+(
+    @options $front:tt $key:tt $idx:tt [$u:tt $s:tt $d:tt $kf:tt $impls:tt $st:tt] unique $(, $($rest:tt)*)?
+) => {
+        $crate::sortedvec! { @options $front $key $idx [[last] $s $d $kf $impls $st] $($($rest)*)? }
+    };
+(
+    @options $front:tt $key:tt $idx:tt [$u:tt $s:tt $d:tt $kf:tt $impls:tt $st:tt]
+    unique = first $(, $($rest:tt)*)?
+) => {
+        $crate::sortedvec! { @options $front $key $idx [[first] $s $d $kf $impls $st] $($($rest)*)? }
+    };
+(
+    @options $front:tt $key:tt $idx:tt [$u:tt $s:tt $d:tt $kf:tt $impls:tt $st:tt]
+    unique = last $(, $($rest:tt)*)?
+) => {
+        $crate::sortedvec! { @options $front $key $idx [[last] $s $d $kf $impls $st] $($($rest)*)? }
+    };
+(
+    @options $front:tt $key:tt $idx:tt [$u:tt $s:tt $d:tt $kf:tt $impls:tt $st:tt]
+    merge = $path:path $(, $($rest:tt)*)?
+) => {
+        $crate::sortedvec! {
+            @options $front $key $idx [[merge $path] $s $d $kf $impls $st] $($($rest)*)?
+        }
+    };
+(
+    @options $front:tt $key:tt $idx:tt [$u:tt $s:tt $d:tt $kf:tt $impls:tt $st:tt] stable $(, $($rest:tt)*)?
+) => {
+        $crate::sortedvec! { @options $front $key $idx [$u true $d $kf $impls $st] $($($rest)*)? }
+    };
+(
+    @options $front:tt $key:tt $idx:tt [$u:tt $s:tt $d:tt $kf:tt $impls:tt $st:tt]
+    order = ascending $(, $($rest:tt)*)?
+) => {
+        $crate::sortedvec! { @options $front $key $idx [$u $s false $kf $impls $st] $($($rest)*)? }
+    };
+(
+    @options $front:tt $key:tt $idx:tt [$u:tt $s:tt $d:tt $kf:tt $impls:tt $st:tt]
+    order = descending $(, $($rest:tt)*)?
+) => {
+        $crate::sortedvec! { @options $front $key $idx [$u $s true $kf $impls $st] $($($rest)*)? }
+    };
+(
+    @options $front:tt $key:tt $idx:tt [$u:tt $s:tt $d:tt $kf:tt $impls:tt $st:tt]
+    key_fn = $path:path $(, $($rest:tt)*)?
+) => {
+        $crate::sortedvec! { @options $front $key $idx [$u $s $d [$path] $impls $st] $($($rest)*)? }
+    };
+(
+    @options $front:tt $key:tt $idx:tt [$u:tt $s:tt $d:tt $kf:tt $impls:tt $st:tt]
+    search = $strategy:ident $(, $($rest:tt)*)?
+) => {
+        $crate::sortedvec! { @options $front $key $idx [$u $s $d $kf $impls $strategy] $($($rest)*)? }
+    };
+(
+    @options $front:tt $key:tt $idx:tt [$u:tt $s:tt $d:tt $kf:tt [$($impls:ident)*] $st:tt]
+    partial_eq $(, $($rest:tt)*)?
+) => {
+        $crate::sortedvec! {
+            @options $front $key $idx [$u $s $d $kf [$($impls)* partial_eq] $st] $($($rest)*)?
+        }
+    };
+(
+    @options $front:tt $key:tt $idx:tt [$u:tt $s:tt $d:tt $kf:tt [$($impls:ident)*] $st:tt]
+    debug_map $(, $($rest:tt)*)?
+) => {
+        $crate::sortedvec! {
+            @options $front $key $idx [$u $s $d $kf [$($impls)* debug_map] $st] $($($rest)*)?
+        }
+    };
+(
+    @options $front:tt $key:tt $idx:tt $opts:tt
+) => {
+        $crate::sortedvec! { @emit $front $key $idx $opts }
+    };
+(
+    @options $front:tt $key:tt $idx:tt $opts:tt $($unknown:tt)+
+) => {
+        compile_error!(concat!("unknown sortedvec option: ", stringify!($($unknown)+)));
+    };
+(
+    @emit [newtype $name:ident $gen:tt $args:tt $bounds:tt $search:tt] [plain $($key:tt)*] []
+    [$u:tt $s:tt $d:tt $kf:tt $impls:tt $st:tt]
+) => {
+        $crate::sortedvec! {
+            @newtype $name $gen $args $bounds $search $($key)* $kf [$u $s $d $impls $st]
+        }
+    };
+(
+    @emit [newtype $($front:tt)*] $key:tt $idx:tt $opts:tt
+) => {
+        compile_error!("secondary indexes, optional keys and a context are not supported on newtypes");
+    };
+(
+    @emit [$($front:tt)*] [plain $($key:tt)*] $idx:tt [$u:tt $s:tt $d:tt $kf:tt $impls:tt $st:tt]
+) => {
+        $crate::sortedvec! { @plain $($front)* $($key)* $kf $idx [$u $s $d $impls $st] }
+    };
+(
+    @emit [$($front:tt)*] [context $($key:tt)*] [] [$u:tt $s:tt $d:tt $kf:tt $impls:tt $st:tt]
+) => {
+        $crate::sortedvec! { @context $($front)* $($key)* $kf [$u $s $d $impls $st] }
+    };
+(
+    @emit [$($front:tt)*] [optional $($key:tt)*] [] [$u:tt $s:tt $d:tt $kf:tt $impls:tt $st:tt]
+) => {
+        $crate::sortedvec! { @optional $($front)* $($key)* $kf [$u $s $d $impls $st] }
+    };
+(
+    @emit $front:tt $key:tt $idx:tt $opts:tt
+) => {
+        compile_error!("secondary indexes cannot be combined with optional keys or a context");
+    };
+(
+    @derive_body $($arg:ident),+ [{ $keyexpr:expr }] []
+) => {
+        $keyexpr
+    };
+(
+    @derive_body $($arg:ident),+ [] [$path:path]
+) => {
+        $path($($arg),+)
+    };
+(
+    @derive_body $($arg:ident),+ $body:tt $kf:tt
+) => {
+        compile_error!("`derive_key` needs either a body or the `key_fn` option, but not both")
+    };
+(
+    @plain [$(#[$attr:meta])*] $v:vis $name:ident
+    [$($gen:tt)*] [$($args:tt)*] [$($bounds:tt)*] $search:tt
+    fn derive_key($i:ident : & $val:ty) -> $key:ty [$($body:tt)*] $kf:tt
+    [$([$find:ident $range:ident] fn $iname:ident($ii:ident : & $ival:ty) -> $ikey:ty { $iexpr:expr })*]
+    $opts:tt
 ) => {
         $(#[$attr])*
-        $v struct $name {
+        $v struct $name<$($gen)*> where $($bounds)* {
             inner: Vec<$val>,
+            $($iname: Vec<usize>,)*
         }
 
         #[allow(dead_code)]
-        impl $name {
-            fn derive_key($i : &$val) -> $key { $keyexpr }
+        impl<$($gen)*> $name<$($args)*> where $($bounds)* {
+            #[allow(clippy::ptr_arg)]
+            fn derive_key($i : &$val) -> $key {
+                $crate::sortedvec!(@derive_body $i [$($body)*] $kf)
+            }
 
-            /// Tries to find an element in the collection with the given key, and return
-            /// its index when found. When it is not present, the index where it should be
-            /// inserted is returned. This method has logarithmic worst case time complexity.
-            pub fn position(&self, key: &$key) -> Result<usize, usize> {
-                self.inner
-                    .binary_search_by(|probe| Self::derive_key(probe).cmp(key))
+            /// Creates a collection from values that are already sorted, checking their order
+            /// in `O(n)` time instead of sorting them. Returns an error holding the values and
+            /// the index of the first value that is out of order otherwise.
+            pub fn try_from_sorted(vec: Vec<$val>) -> Result<Self, $crate::NotSorted<$val>> {
+                match Self::first_unsorted(&vec, |val| Some(Self::derive_key(val))) {
+                    Some(index) => Err($crate::NotSorted::new(index, vec)),
+                    None => Ok(unsafe { Self::from_sorted_unchecked(vec) }),
+                }
             }
 
-            /// Tries to find an element in the collection with the given key. It has
-            /// logarithmic worst case time complexity.
-            pub fn find(&self, key: &$key) -> Option<&$val> {
-                // The unsafe block is OK because `position` is guaranteed to
-                // return a valid index.
-                self.position(key)
-                    .ok()
-                    .map(|idx| unsafe { self.inner.get_unchecked(idx) })
+            /// Creates a collection from values that are already sorted, without checking
+            /// their order.
+            ///
+            /// # Safety
+            ///
+            /// The values must be sorted by the keys that `derive_key` returns for them, in the
+            /// order of the collection and without duplicate keys when it is `unique`. Lookups
+            /// rely on this order to skip bounds checks, so breaking it may cause undefined
+            /// behavior.
+            pub unsafe fn from_sorted_unchecked(vec: Vec<$val>) -> Self {
+                let mut res = Self { inner: vec, $($iname: Vec::new(),)* };
+                res.reindex();
+                res.check_invariants();
+                res
             }
 
-            /// Checks whether there is a value with that key in the collection. This is
-            /// done in `O(log(n))` time.
-            pub fn contains(&self, key: &$key) -> bool {
-                self.position(key).is_ok()
+            // private method
+            fn key_fn(&self) -> fn(&$val) -> $key {
+                Self::derive_key
             }
 
-            /// Removes and returns a single value from the collection with the given key,
-            /// if it exists. This operation has linear worst-case time complexity.
-            pub fn remove(&mut self, key: &$key) -> Option<$val> {
-                self.position(key)
-                    .ok()
-                    .map(|idx| self.inner.remove(idx))
+            // private method
+            fn inner_and_key_fn(&mut self) -> (&mut Vec<$val>, fn(&$val) -> $key) {
+                (&mut self.inner, Self::derive_key)
             }
 
-            /// Inserts a new value into the collection, maintaining the internal
-            /// order invariant. This is an `O(n)` operation.
-            pub fn insert(&mut self, val: $val) {
-                let ref key = Self::derive_key(&val);
-                let idx = match self.position(key) {
-                    Ok(i) | Err(i) => i,
-                };
-                self.inner.insert(idx, val);
+            // private method
+            fn key_context(&self) -> &() {
+                &()
             }
 
-            /// Splits the collection into two at the given index.
-            ///
-            /// Returns a newly allocated `Self`. `self` contains elements `[0, at)`,
-            /// and the returned `Self` contains elements `[at, len)`.
-            ///
-            /// Note that the capacity of `self` does not change.
-            ///
-            /// # Panics
-            ///
-            /// Panics if `at > len`.
-            pub fn split_off(&mut self, at: usize) -> Self {
-                let other_inner = self.inner.split_off(at);
-                Self {
-                    inner: other_inner,
-                }
+            // private method
+            fn with_inner(&self, inner: Vec<$val>) -> Self {
+                let mut res = Self { inner, $($iname: Vec::new(),)* };
+                res.reindex();
+                res
             }
 
-            /// Removes all elements but one that resolve to the same key.
-            pub fn dedup(&mut self) {
-                self.inner.dedup_by(|a, b| Self::derive_key(a) == Self::derive_key(b));
+            // private method
+            fn into_vec(self) -> Vec<$val> {
+                self.inner
             }
 
-            /// Removes and returns the greatest element with the respect to
-            /// the generated keys. An `O(1)` operation.
-            pub fn pop(&mut self) -> Option<$val> {
-                self.inner.pop()
+            // private method
+            fn route_unkeyed(&mut self, val: $val) -> Option<$val> {
+                Some(val)
             }
 
             // private method
-            fn sort(&mut self) {
-                self.inner.sort_unstable_by(|a, b| {
-                    let lhs = Self::derive_key(a);
-                    let rhs = Self::derive_key(b);
-                    lhs.cmp(&rhs)
-                })
+            fn separate_unkeyed(&mut self) {}
+
+            // private method
+            fn set_op_unkeyed(&mut self, _other: &mut Self, _op: $crate::merge::Op) {}
+
+            // private method
+            fn adopt_context(&self, other: Self) -> Self {
+                other
             }
-        }
 
-        impl std::default::Default for $name {
-            fn default() -> Self {
-                Self { inner: std::default::Default::default() }
+            // private method
+            fn unkey_at(&mut self, _index: usize) -> bool {
+                false
             }
-        }
 
-        impl Extend<$val> for $name {
-            fn extend<I>(&mut self, iter: I)
-            where
-                I: IntoIterator<Item = $val>,
-            {
-                self.inner.extend(iter);
-                self.sort();
+            // private method
+            fn patch_unkeyed(
+                &mut self,
+                change: $crate::diff::Change<$val>,
+                _eq: impl Fn(&$val, &$val) -> bool,
+            ) -> Option<$crate::diff::Change<$val>> {
+                Some(change)
             }
-        }
 
-        impl std::iter::FromIterator<$val> for $name {
-            fn from_iter<I: std::iter::IntoIterator<Item=$val>>(iter: I) -> Self {
-                let inner = Vec::from_iter(iter);
-                From::from(inner)
+            $(
+                #[allow(clippy::ptr_arg)]
+                fn $iname($ii : &$ival) -> $ikey { $iexpr }
+
+                /// Tries to find an element in the collection with the given secondary key. It
+                /// has logarithmic worst case time complexity.
+                pub fn $find(&self, key: &$ikey) -> Option<&$val> {
+                    let inner = &self.inner;
+                    self.$iname
+                        .binary_search_by(|&i| Self::$iname(&inner[i]).cmp(key))
+                        .ok()
+                        .map(|pos| &inner[self.$iname[pos]])
+                }
+
+                /// Returns an iterator over the elements whose secondary key lies between the
+                /// given bounds, ordered by that key. Locating the bounds takes logarithmic time.
+                pub fn $range(
+                    &self,
+                    start: std::ops::Bound<&$ikey>,
+                    end: std::ops::Bound<&$ikey>,
+                ) -> impl Iterator<Item = &$val> + '_ {
+                    use std::ops::Bound;
+
+                    let inner = &self.inner;
+                    let index = &self.$iname;
+                    let key_at = |i: &usize| Self::$iname(&inner[*i]);
+                    let lo = match start {
+                        Bound::Included(key) => index.partition_point(|i| key_at(i) < *key),
+                        Bound::Excluded(key) => index.partition_point(|i| key_at(i) <= *key),
+                        Bound::Unbounded => 0,
+                    };
+                    let hi = match end {
+                        Bound::Included(key) => index.partition_point(|i| key_at(i) <= *key),
+                        Bound::Excluded(key) => index.partition_point(|i| key_at(i) < *key),
+                        Bound::Unbounded => index.len(),
+                    };
+                    index[lo..std::cmp::max(lo, hi)].iter().map(move |&i| &inner[i])
+                }
+            )*
+
+            // private method
+            fn reindex(&mut self) {
+                $(
+                    let inner = &self.inner;
+                    let mut index: Vec<usize> = (0..inner.len()).collect();
+                    index.sort_by(|&a, &b| Self::$iname(&inner[a]).cmp(&Self::$iname(&inner[b])));
+                    self.$iname = index;
+                )*
             }
-        }
 
-        impl std::iter::IntoIterator for $name {
-            type Item = $val;
-            type IntoIter = std::vec::IntoIter<$val>;
+            // private method
+            #[allow(unused_variables)]
+            fn index_inserted(&mut self, idx: usize) {
+                $(
+                    for i in self.$iname.iter_mut() {
+                        if *i >= idx {
+                            *i += 1;
+                        }
+                    }
+                    let inner = &self.inner;
+                    let key = Self::$iname(&inner[idx]);
+                    let pos = self.$iname.partition_point(|&i| Self::$iname(&inner[i]) <= key);
+                    self.$iname.insert(pos, idx);
+                )*
+            }
 
-            fn into_iter(self) -> Self::IntoIter {
-                self.inner.into_iter()
+            // private method
+            #[allow(unused_variables)]
+            fn index_removed(&mut self, idx: usize) {
+                $(
+                    self.$iname.retain(|&i| i != idx);
+                    for i in self.$iname.iter_mut() {
+                        if *i > idx {
+                            *i -= 1;
+                        }
+                    }
+                )*
+            }
+
+            // private method
+            #[allow(unused_variables)]
+            fn index_truncated(&mut self, len: usize) {
+                $(
+                    self.$iname.retain(|&i| i < len);
+                )*
             }
         }
 
-        impl Into<Vec<$val>> for $name {
-            fn into(self) -> Vec<$val> {
-                self.inner
+        impl<$($gen)*> std::default::Default for $name<$($args)*> where $($bounds)* {
+            fn default() -> Self {
+                Self { inner: std::default::Default::default(), $($iname: Vec::new(),)* }
+            }
+        }
+
+        impl<$($gen)*> std::iter::FromIterator<$val> for $name<$($args)*> where $($bounds)* {
+            fn from_iter<Iter: std::iter::IntoIterator<Item=$val>>(iter: Iter) -> Self {
+                let inner = Vec::from_iter(iter);
+                From::from(inner)
             }
         }
 
-        impl From<Vec<$val>> for $name {
+        impl<$($gen)*> From<Vec<$val>> for $name<$($args)*> where $($bounds)* {
             fn from(vec: Vec<$val>) -> Self {
-                let mut res = Self { inner: vec };
+                let mut res = Self { inner: vec, $($iname: Vec::new(),)* };
                 res.sort();
                 res
             }
         }
 
-        impl std::ops::Deref for $name {
-            type Target = Vec<$val>;
+        $crate::sortedvec! {
+            @impl $name [$($gen)*] [$($args)*] [$($bounds)*],
+            $val, $key, (), fn(&$val) -> $key, derive_key, inner, $search, $opts
+        }
+    };
+(
+    @optional [$(#[$attr:meta])*] $v:vis $name:ident
+    [$($gen:tt)*] [$($args:tt)*] [$($bounds:tt)*] $search:tt
+    fn derive_key($i:ident : & $val:ty) -> Option<$key:ty> [$($body:tt)*] $kf:tt
+    $opts:tt
+) => {
+        $(#[$attr])*
+        $v struct $name<$($gen)*> where $($bounds)* {
+            inner: Vec<$val>,
+            unkeyed: Vec<$val>,
+        }
 
-            fn deref(&self) -> &Self::Target {
-                &self.inner
+        #[allow(dead_code)]
+        impl<$($gen)*> $name<$($args)*> where $($bounds)* {
+            #[allow(clippy::ptr_arg)]
+            fn derive_key($i : &$val) -> Option<$key> {
+                $crate::sortedvec!(@derive_body $i [$($body)*] $kf)
+            }
+
+            // private method
+            fn known_key(val: &$val) -> $key {
+                match Self::derive_key(val) {
+                    Some(key) => key,
+                    None => unreachable!("unkeyed value in sorted part of collection"),
+                }
+            }
+
+            /// Creates a collection from values whose keys are already sorted, checking their
+            /// order in `O(n)` time instead of sorting them. Values without a key may occur
+            /// anywhere. Returns an error holding the values and the index of the first value
+            /// that is out of order otherwise.
+            pub fn try_from_sorted(vec: Vec<$val>) -> Result<Self, $crate::NotSorted<$val>> {
+                match Self::first_unsorted(&vec, Self::derive_key) {
+                    Some(index) => Err($crate::NotSorted::new(index, vec)),
+                    None => Ok(unsafe { Self::from_sorted_unchecked(vec) }),
+                }
+            }
+
+            /// Creates a collection from values whose keys are already sorted, without
+            /// checking their order.
+            ///
+            /// # Safety
+            ///
+            /// The values with a key must be sorted by the keys that `derive_key` returns for
+            /// them, in the order of the collection and without duplicate keys when it is
+            /// `unique`. Lookups rely on this order to skip bounds checks, so breaking it may
+            /// cause undefined behavior.
+            pub unsafe fn from_sorted_unchecked(vec: Vec<$val>) -> Self {
+                let mut res = Self { inner: vec, unkeyed: Vec::new() };
+                res.separate_unkeyed();
+                res.check_invariants();
+                res
+            }
+
+            // private method
+            fn key_fn(&self) -> fn(&$val) -> $key {
+                Self::known_key
+            }
+
+            // private method
+            fn inner_and_key_fn(&mut self) -> (&mut Vec<$val>, fn(&$val) -> $key) {
+                (&mut self.inner, Self::known_key)
+            }
+
+            // private method
+            fn key_context(&self) -> &() {
+                &()
+            }
+
+            // private method
+            fn with_inner(&self, inner: Vec<$val>) -> Self {
+                Self { inner, unkeyed: Vec::new() }
             }
+
+            // private method
+            fn into_vec(self) -> Vec<$val> {
+                let mut vec = self.inner;
+                vec.extend(self.unkeyed);
+                vec
+            }
+
+            // private method
+            fn route_unkeyed(&mut self, val: $val) -> Option<$val> {
+                if Self::derive_key(&val).is_some() {
+                    Some(val)
+                } else {
+                    self.unkeyed.push(val);
+                    None
+                }
+            }
+
+            // private method
+            fn separate_unkeyed(&mut self) {
+                let (keyed, unkeyed) = std::mem::take(&mut self.inner)
+                    .into_iter()
+                    .partition(|val| Self::derive_key(val).is_some());
+                self.inner = keyed;
+                self.unkeyed.extend::<Vec<$val>>(unkeyed);
+            }
+
+            // private method
+            fn set_op_unkeyed(&mut self, other: &mut Self, op: $crate::merge::Op) {
+                if !op.keeps_left() {
+                    self.unkeyed.clear();
+                }
+                if op.keeps_right() {
+                    self.unkeyed.append(&mut other.unkeyed);
+                }
+            }
+
+            // private method
+            fn adopt_context(&self, other: Self) -> Self {
+                other
+            }
+
+            // private method
+            fn unkey_at(&mut self, index: usize) -> bool {
+                if Self::derive_key(&self.inner[index]).is_some() {
+                    return false;
+                }
+                let val = self.inner.remove(index);
+                self.unkeyed.push(val);
+                true
+            }
+
+            // private method
+            fn patch_unkeyed(
+                &mut self,
+                change: $crate::diff::Change<$val>,
+                eq: impl Fn(&$val, &$val) -> bool,
+            ) -> Option<$crate::diff::Change<$val>> {
+                use $crate::diff::Change;
+
+                if Self::derive_key(change.value()).is_some() {
+                    return Some(change);
+                }
+                match change {
+                    Change::Added(val) => self.unkeyed.push(val),
+                    Change::Removed(val) => {
+                        if let Some(idx) = self.unkeyed.iter().position(|kept| eq(kept, &val)) {
+                            self.unkeyed.remove(idx);
+                        }
+                    }
+                    Change::Changed(prev, val) => {
+                        match self.unkeyed.iter().position(|kept| eq(kept, &prev)) {
+                            Some(idx) => self.unkeyed[idx] = val,
+                            None => self.unkeyed.push(val),
+                        }
+                    }
+                }
+                None
+            }
+
+            /// Returns the values for which no key could be derived, in insertion order.
+            /// These values are not found by any of the lookup methods.
+            pub fn unkeyed(&self) -> &[$val] {
+                &self.unkeyed
+            }
+
+            /// Removes and returns all values for which no key could be derived.
+            pub fn take_unkeyed(&mut self) -> Vec<$val> {
+                std::mem::take(&mut self.unkeyed)
+            }
+
+            // private method
+            fn reindex(&mut self) {}
+
+            // private method
+            fn index_inserted(&mut self, _idx: usize) {}
+
+            // private method
+            fn index_removed(&mut self, _idx: usize) {}
+
+            // private method
+            fn index_truncated(&mut self, _len: usize) {}
         }
 
-        impl std::borrow::Borrow<[$val]> for $name {
-            fn borrow(&self) -> &[$val] {
-                &self.inner
+        impl<$($gen)*> std::default::Default for $name<$($args)*> where $($bounds)* {
+            fn default() -> Self {
+                Self { inner: Vec::new(), unkeyed: Vec::new() }
             }
         }
 
-        impl AsRef<[$val]> for $name {
-            fn as_ref(&self) -> &[$val] {
-                &self.inner
+        impl<$($gen)*> std::iter::FromIterator<$val> for $name<$($args)*> where $($bounds)* {
+            fn from_iter<Iter: std::iter::IntoIterator<Item=$val>>(iter: Iter) -> Self {
+                let inner = Vec::from_iter(iter);
+                From::from(inner)
             }
         }
 
-        impl AsRef<Vec<$val>> for $name {
-            fn as_ref(&self) -> &Vec<$val> {
-                &self.inner
+        impl<$($gen)*> From<Vec<$val>> for $name<$($args)*> where $($bounds)* {
+            fn from(vec: Vec<$val>) -> Self {
+                let mut res = Self { inner: vec, unkeyed: Vec::new() };
+                res.sort();
+                res
             }
         }
-    }
-}
 
-/// A macro that defines a specialized sorted vector data structure on [slice] keys.
-///
-/// It differs from the standard
-/// `sortedvec!` macro in that the generated data structure is sorted on slices. This enables binary
-/// searches to be a bit smarter by skipping the comparison of the start of the slice that was shared
-/// with probes smaller and larger than the current probe.
-///
-/// Note that when your key can be compared as `&[u8]`s, like `&str`, a regular `sortedvec!`
-/// may still be faster as SIMD instructions can be used to compare long byte sequences and there is
-/// less bookkeeping involved.
-///
-/// The generated struct is specific to the given keys and value types. To create the struct,
-/// four bits are required:
-/// - a struct name,
-/// - a value type,
-/// - a slice key type of the form `&[K]`. Since we will sort on these internally, `K` must implement `Ord`,
-/// - a key extraction function of type `FnMut(&T) -> &[K]`.
-///
-/// It matches the following input:
-/// ```text
-/// $(#[$attr:meta])*
-/// $v:vis struct $name:ident {
-///     fn derive_key($i:ident : & $val:ty) -> & [ $key:ty ] {
-///         $keyexpr:expr
-///     } $(,)?
-/// }
-/// ```
-///
-/// The exposed methods are identical to that of a data structure generated by `sortedvec!`.
-/// To get an overview of the exposed methods on the generated structure, see the documentation
-/// of the example module.
-///
-/// [slice]: https://doc.rust-lang.org/std/primitive.slice.html
-#[macro_export]
-macro_rules! sortedvec_slicekey {
+        $crate::sortedvec! {
+            @impl $name [$($gen)*] [$($args)*] [$($bounds)*],
+            $val, $key, (), fn(&$val) -> $key, known_key, inner, $search, $opts
+        }
+    };
 (
-    $(#[$attr:meta])*
-    $v:vis struct $name:ident {
-        fn derive_key($i:ident : & $val:ty) -> & [ $key:ty ] {
-            $keyexpr:expr
-        } $(,)?
-    }
+    @context [$(#[$attr:meta])*] $v:vis $name:ident
+    [$($gen:tt)*] [$($args:tt)*] [$($bounds:tt)*] $search:tt
+    fn derive_key($c:ident : & $ctx:ty, $i:ident : & $val:ty) -> $key:ty [$($body:tt)*] $kf:tt
+    $opts:tt
 ) => {
         $(#[$attr])*
-        $v struct $name {
+        $v struct $name<$($gen)*> where $($bounds)* {
             inner: Vec<$val>,
+            context: $ctx,
         }
 
         #[allow(dead_code)]
-        impl $name {
-            fn derive_key($i : &$val) -> & [ $key ] { $keyexpr }
+        impl<$($gen)*> $name<$($args)*> where $($bounds)* {
+            #[allow(clippy::ptr_arg)]
+            fn derive_key($c : &$ctx, $i : &$val) -> $key {
+                $crate::sortedvec!(@derive_body $c, $i [$($body)*] $kf)
+            }
 
-            /// Tries to find an element in the collection with the given key, and return
-            /// its index when found. When it is not present, the index where it should be
-            /// inserted is returned. This method has logarithmic worst case time complexity.
-            pub fn position<E: AsRef<[$key]>>(&self, init_key: E) -> Result<usize, usize> {
-                let mut size = self.inner.len();
-                let mut upper_shared_prefix = 0;
-                let mut lower_shared_prefix = 0;
-                if size == 0 {
-                    return Err(0);
+            // private method
+            fn key_fn(&self) -> impl Fn(&$val) -> $key + '_ {
+                move |val| Self::derive_key(&self.context, val)
+            }
+
+            // private method
+            fn inner_and_key_fn(&mut self) -> (&mut Vec<$val>, impl Fn(&$val) -> $key + '_) {
+                let context = &self.context;
+                (&mut self.inner, move |val: &$val| Self::derive_key(context, val))
+            }
+
+            // private method
+            fn key_context(&self) -> &$ctx {
+                &self.context
+            }
+
+            // private method
+            fn with_inner(&self, inner: Vec<$val>) -> Self {
+                Self { inner, context: self.context.clone() }
+            }
+
+            // private method
+            fn into_vec(self) -> Vec<$val> {
+                self.inner
+            }
+
+            // private method
+            fn route_unkeyed(&mut self, val: $val) -> Option<$val> {
+                Some(val)
+            }
+
+            // private method
+            fn separate_unkeyed(&mut self) {}
+
+            // private method
+            fn set_op_unkeyed(&mut self, _other: &mut Self, _op: $crate::merge::Op) {}
+
+            // private method
+            fn adopt_context(&self, other: Self) -> Self {
+                let mut other = self.with_inner(other.inner);
+                let key_of = |val: &$val| Some(Self::derive_key(&self.context, val));
+                if Self::first_unsorted(&other.inner, key_of).is_some() {
+                    other.sort();
                 }
-                let key_as_slice = init_key.as_ref();
-                let mut base = 0usize;
-                while size > 1 {
-                    let half = size / 2;
-                    let mid = base + half;
-                    let prefix_skip = std::cmp::min(lower_shared_prefix, upper_shared_prefix);
-                    // mid is always in [0, size), that means mid is >= 0 and < size.
-                    // mid >= 0: by definition
-                    // mid < size: mid = size / 2 + size / 4 + size / 8 ...
-                    let elt = unsafe { self.inner.get_unchecked(mid) };
-                    let key = Self::derive_key(elt);
-                    let (prefix_len, cmp) = unsafe {
-                        Self::compare(key.get_unchecked(prefix_skip..), key_as_slice.get_unchecked(prefix_skip..))
-                    };
-                    base = match cmp {
-                        std::cmp::Ordering::Greater => {
-                            upper_shared_prefix = prefix_skip + prefix_len;
-                            base
-                        }
-                        std::cmp::Ordering::Less => {
-                            lower_shared_prefix = prefix_skip + prefix_len;
-                            mid
-                        }
-                        std::cmp::Ordering::Equal => return Ok(mid),
-                    };
-                    size -= half;
+                other
+            }
+
+            // private method
+            fn unkey_at(&mut self, _index: usize) -> bool {
+                false
+            }
+
+            // private method
+            fn patch_unkeyed(
+                &mut self,
+                change: $crate::diff::Change<$val>,
+                _eq: impl Fn(&$val, &$val) -> bool,
+            ) -> Option<$crate::diff::Change<$val>> {
+                Some(change)
+            }
+
+            /// Creates an empty collection whose keys are derived using the given context.
+            pub fn with_context(context: $ctx) -> Self {
+                Self { inner: Vec::new(), context }
+            }
+
+            /// Creates a collection from the given values, whose keys are derived using the
+            /// given context. This sorts the values, which is an `O(n log(n))` operation.
+            pub fn from_vec_with_context(vec: Vec<$val>, context: $ctx) -> Self {
+                let mut res = Self { inner: vec, context };
+                res.sort();
+                res
+            }
+
+            /// Creates a collection from values that are already sorted, checking their order
+            /// in `O(n)` time instead of sorting them, like `try_from_sorted` on other sorted
+            /// vectors.
+            pub fn try_from_sorted_with_context(
+                vec: Vec<$val>,
+                context: $ctx,
+            ) -> Result<Self, $crate::NotSorted<$val>> {
+                match Self::first_unsorted(&vec, |val| Some(Self::derive_key(&context, val))) {
+                    Some(index) => Err($crate::NotSorted::new(index, vec)),
+                    None => Ok(Self { inner: vec, context }),
                 }
-                let prefix_skip = std::cmp::min(lower_shared_prefix, upper_shared_prefix);
-                // base is always in [0, size) because base <= mid.
-                let elt = unsafe { self.inner.get_unchecked(base) };
-                let key = unsafe { &Self::derive_key(&elt).get_unchecked(prefix_skip..) };
-                let (_prefix, cmp) = unsafe { Self::compare(key, key_as_slice.get_unchecked(prefix_skip..)) };
-                if cmp == std::cmp::Ordering::Equal { Ok(base) } else { Err(base) }
             }
 
-            #[inline]
-            fn compare(slice: &[$key], other: &[$key]) -> (usize, std::cmp::Ordering) {
-                let l = std::cmp::min(slice.len(), other.len());
-                let mut prefix_len = 0;
+            /// Creates a collection from values that are already sorted, without checking
+            /// their order, like `from_sorted_unchecked` on other sorted vectors.
+            ///
+            /// # Safety
+            ///
+            /// The values must be sorted by the keys that `derive_key` returns for them given
+            /// `context`, in the order of the collection and without duplicate keys when it is
+            /// `unique`. Lookups rely on this order to skip bounds checks, so breaking it may
+            /// cause undefined behavior.
+            pub unsafe fn from_sorted_unchecked_with_context(
+                vec: Vec<$val>,
+                context: $ctx,
+            ) -> Self {
+                let res = Self { inner: vec, context };
+                res.check_invariants();
+                res
+            }
 
-                // Slice to the loop iteration range to enable bound check
-                // elimination in the compiler
-                let lhs = &slice[..l];
-                let rhs = &other[..l];
+            /// Returns a reference to the context that is used to derive keys.
+            pub fn context(&self) -> &$ctx {
+                &self.context
+            }
 
-                for i in 0..l {
-                    match lhs[i].cmp(&rhs[i]) {
-                        std::cmp::Ordering::Equal => { prefix_len += 1 }
-                        non_eq => return (prefix_len, non_eq),
+            // private method
+            fn reindex(&mut self) {}
+
+            // private method
+            fn index_inserted(&mut self, _idx: usize) {}
+
+            // private method
+            fn index_removed(&mut self, _idx: usize) {}
+
+            // private method
+            fn index_truncated(&mut self, _len: usize) {}
+        }
+
+        $crate::sortedvec! {
+            @impl $name [$($gen)*] [$($args)*] [$($bounds)*],
+            $val, $key, $ctx, fn(&$ctx, &$val) -> $key, derive_key, inner, $search, $opts
+        }
+    };
+(
+    @newtype $name:ident [$($gen:tt)*] [$($args:tt)*] [$($bounds:tt)*] $search:tt
+    fn derive_key($i:ident : & $val:ty) -> $key:ty [$($body:tt)*] $kf:tt
+    $opts:tt
+) => {
+        #[allow(dead_code)]
+        impl<$($gen)*> $name<$($args)*> where $($bounds)* {
+            #[allow(clippy::ptr_arg)]
+            fn derive_key($i : &$val) -> $key {
+                $crate::sortedvec!(@derive_body $i [$($body)*] $kf)
+            }
+
+            /// Creates a collection from values that are already sorted, checking their order
+            /// in `O(n)` time instead of sorting them. Returns an error holding the values and
+            /// the index of the first value that is out of order otherwise.
+            pub fn try_from_sorted(vec: Vec<$val>) -> Result<Self, $crate::NotSorted<$val>> {
+                match Self::first_unsorted(&vec, |val| Some(Self::derive_key(val))) {
+                    Some(index) => Err($crate::NotSorted::new(index, vec)),
+                    None => Ok(Self(vec)),
+                }
+            }
+
+            /// Creates a collection from values that are already sorted, without checking
+            /// their order.
+            ///
+            /// # Safety
+            ///
+            /// The values must be sorted by the keys that `derive_key` returns for them, in the
+            /// order of the collection and without duplicate keys when it is `unique`. Lookups
+            /// rely on this order to skip bounds checks, so breaking it may cause undefined
+            /// behavior.
+            pub unsafe fn from_sorted_unchecked(vec: Vec<$val>) -> Self {
+                let res = Self(vec);
+                res.check_invariants();
+                res
+            }
+
+            // private method
+            fn key_fn(&self) -> fn(&$val) -> $key {
+                Self::derive_key
+            }
+
+            // private method
+            fn inner_and_key_fn(&mut self) -> (&mut Vec<$val>, fn(&$val) -> $key) {
+                (&mut self.0, Self::derive_key)
+            }
+
+            // private method
+            fn key_context(&self) -> &() {
+                &()
+            }
+
+            // private method
+            fn with_inner(&self, inner: Vec<$val>) -> Self {
+                Self(inner)
+            }
+
+            // private method
+            fn into_vec(self) -> Vec<$val> {
+                self.0
+            }
+
+            // private method
+            fn route_unkeyed(&mut self, val: $val) -> Option<$val> {
+                Some(val)
+            }
+
+            // private method
+            fn separate_unkeyed(&mut self) {}
+
+            // private method
+            fn set_op_unkeyed(&mut self, _other: &mut Self, _op: $crate::merge::Op) {}
+
+            // private method
+            fn adopt_context(&self, other: Self) -> Self {
+                other
+            }
+
+            // private method
+            fn unkey_at(&mut self, _index: usize) -> bool {
+                false
+            }
+
+            // private method
+            fn patch_unkeyed(
+                &mut self,
+                change: $crate::diff::Change<$val>,
+                _eq: impl Fn(&$val, &$val) -> bool,
+            ) -> Option<$crate::diff::Change<$val>> {
+                Some(change)
+            }
+
+            // private method
+            fn reindex(&mut self) {}
+
+            // private method
+            fn index_inserted(&mut self, _idx: usize) {}
+
+            // private method
+            fn index_removed(&mut self, _idx: usize) {}
+
+            // private method
+            fn index_truncated(&mut self, _len: usize) {}
+        }
+
+        impl<$($gen)*> Default for $name<$($args)*> where $($bounds)* {
+            fn default() -> Self {
+                Self(Vec::new())
+            }
+        }
+
+        impl<$($gen)*> std::iter::FromIterator<$val> for $name<$($args)*> where $($bounds)* {
+            fn from_iter<Iter: IntoIterator<Item = $val>>(iter: Iter) -> Self {
+                let inner = Vec::from_iter(iter);
+                Self::from(inner)
+            }
+        }
+
+        impl<$($gen)*> From<Vec<$val>> for $name<$($args)*> where $($bounds)* {
+            fn from(vec: Vec<$val>) -> Self {
+                let mut res = Self(vec);
+                res.sort();
+                res
+            }
+        }
+
+        $crate::sortedvec! {
+            @impl $name [$($gen)*] [$($args)*] [$($bounds)*],
+            $val, $key, (), fn(&$val) -> $key, derive_key, 0, $search, $opts
+        }
+    };
+(
+    @lookup scalar $strategy:ident $name:ident [$($gen:tt)*] [$($args:tt)*] [$($bounds:tt)*]
+    $val:ty, $key:ty, $field:tt
+) => {
+        $crate::sortedvec! {
+            @search $strategy $name [$($gen)*] [$($args)*] [$($bounds)*] $val, $key, $field
+        }
+
+        #[allow(dead_code)]
+        impl<$($gen)*> $name<$($args)*> where $($bounds)* {
+            /// Tries to find an element in the collection with the given key, and return
+            /// its index when found. When it is not present, the index where it should be
+            /// inserted is returned. This method has logarithmic worst case time complexity.
+            ///
+            /// When several elements have the given key, the index of any of them may be
+            /// returned, unless the collection is `stable`, in which case it is the first.
+            pub fn position(&self, key: &$key) -> Result<usize, usize> {
+                self.search_position(key)
+            }
+
+            /// Like `position`, but starts searching at `hint` and gallops outward from there,
+            /// so that lookups near the previous one are fast. This takes `O(log(d))` time,
+            /// where `d` is the distance between the hint and the returned index. Hints past the
+            /// end of the collection are treated as its length.
+            ///
+            /// When several elements have the given key, the index of the first is returned. This
+            /// is stricter than `position`, which only returns the first on `stable` collections,
+            /// but both always agree on whether the key is present.
+            pub fn position_from_hint(&self, key: &$key, hint: usize) -> Result<usize, usize> {
+                let key_of = self.key_fn();
+                let idx = $crate::merge::partition_point_from(&self.$field, hint, |probe| {
+                    Self::cmp_keys(&key_of(probe), key) == std::cmp::Ordering::Less
+                });
+                match self.$field.get(idx) {
+                    Some(probe) if key_of(probe) == *key => Ok(idx),
+                    _ => Err(idx),
+                }
+            }
+
+            /// Resolves a batch of keys at once, in the order of the keys. The keys are searched
+            /// for in groups of 8, whose binary searches run in lockstep to hide memory latency.
+            /// Every search in a group starts on the side of the result for the last key of the
+            /// previous group where its key lies, so that sorted keys search less of the
+            /// collection.
+            ///
+            /// When several elements have a key, the index of the first is returned, like
+            /// `position_from_hint` does. `position` only guarantees this on `stable` collections,
+            /// but both always agree on whether a key is present.
+            pub fn positions_many(&self, keys: &[$key]) -> Vec<Result<usize, usize>> {
+                let key_of = self.key_fn();
+                let bounds = self.lower_bounds(keys.len(), |probe, query| {
+                    Self::cmp_keys(&key_of(probe), &keys[query]) == std::cmp::Ordering::Less
+                });
+                bounds
+                    .into_iter()
+                    .zip(keys)
+                    .map(|(idx, key)| match self.$field.get(idx) {
+                        Some(probe) if key_of(probe) == *key => Ok(idx),
+                        _ => Err(idx),
+                    })
+                    .collect()
+            }
+
+            /// Finds the elements with the given keys, in the order of the keys, like
+            /// `positions_many`.
+            pub fn find_many(&self, keys: &[$key]) -> Vec<Option<&$val>> {
+                self.positions_many(keys)
+                    .into_iter()
+                    .map(|res| res.ok().map(|idx| &self.$field[idx]))
+                    .collect()
+            }
+
+            /// Tries to find an element in the collection with the given key. It has
+            /// logarithmic worst case time complexity.
+            pub fn find(&self, key: &$key) -> Option<&$val> {
+                // The unsafe block is OK because `position` is guaranteed to
+                // return a valid index.
+                self.position(key)
+                    .ok()
+                    .map(|idx| unsafe { self.$field.get_unchecked(idx) })
+            }
+
+            /// Checks whether there is a value with that key in the collection. This is
+            /// done in `O(log(n))` time.
+            pub fn contains(&self, key: &$key) -> bool {
+                self.position(key).is_ok()
+            }
+
+            /// Returns a cursor positioned before the value with the given key, or before the
+            /// position where it would be inserted when it is not present.
+            pub fn cursor_at(&self, key: &$key) -> $crate::cursor::Cursor<'_, $val> {
+                match self.position(key) {
+                    Ok(idx) | Err(idx) => $crate::cursor::Cursor::new(&self.$field, idx),
+                }
+            }
+
+            /// Returns a cursor like `cursor_at` that can also remove and insert values.
+            pub fn cursor_mut_at(&mut self, key: &$key) -> $crate::cursor::CursorMut<'_, Self> {
+                match self.position(key) {
+                    Ok(idx) | Err(idx) => $crate::cursor::CursorMut::new(self, idx),
+                }
+            }
+
+            /// Applies `f` to a value with the given key, if any, and moves it to the position
+            /// of its new key like `update_key_at`. Returns the new index of the value, as
+            /// `update_key_at` does.
+            pub fn rekey(&mut self, key: &$key, f: impl FnOnce(&mut $val)) -> Option<usize> {
+                let idx = self.position(key).ok()?;
+                Some(self.update_key_at(idx, f))
+            }
+
+            /// Removes and returns a single value from the collection with the given key,
+            /// if it exists. This operation has linear worst-case time complexity.
+            pub fn remove(&mut self, key: &$key) -> Option<$val> {
+                self.position(key).ok().map(|idx| {
+                    let val = self.$field.remove(idx);
+                    self.index_removed(idx);
+                    val
+                })
+            }
+        }
+
+        impl<$($gen)*> $crate::frozen::FrozenLookup<$key> for $name<$($args)*> where $($bounds)* {
+            fn cmp_key(&self, val: &$val, key: &$key) -> std::cmp::Ordering {
+                Self::cmp_keys(&self.key_fn()(val), key)
+            }
+        }
+
+        $crate::sortedvec! {
+            @collection $name [$($gen)*] [$($args)*] [$($bounds)*] $val, $key, $field
+        }
+    };
+(
+    @lookup [slice $elem:ty] $strategy:ident $name:ident [$($gen:tt)*] [$($args:tt)*] [$($bounds:tt)*]
+    $val:ty, $key:ty, $field:tt
+) => {
+        $crate::sortedvec! {
+            @search_slice $strategy $name [$($gen)*] [$($args)*] [$($bounds)*] $val, $elem, $field
+        }
+
+        #[allow(dead_code)]
+        impl<$($gen)*> $name<$($args)*> where $($bounds)* {
+            /// Tries to find an element in the collection with the given key, and return
+            /// its index when found. When it is not present, the index where it should be
+            /// inserted is returned. This method has logarithmic worst case time complexity.
+            ///
+            /// When several elements have the given key, the index of any of them may be
+            /// returned, unless the collection is `stable`, in which case it is the first.
+            pub fn position<KeySlice: AsRef<[$elem]>>(&self, init_key: KeySlice) -> Result<usize, usize> {
+                self.search_position(init_key.as_ref())
+            }
+
+            /// Like `position`, but starts searching at `hint` and gallops outward from there,
+            /// so that lookups near the previous one are fast. This takes `O(log(d))` time,
+            /// where `d` is the distance between the hint and the returned index. Hints past the
+            /// end of the collection are treated as its length.
+            ///
+            /// When several elements have the given key, the index of the first is returned. This
+            /// is stricter than `position`, which only returns the first on `stable` collections,
+            /// but both always agree on whether the key is present.
+            pub fn position_from_hint<KeySlice: AsRef<[$elem]>>(
+                &self,
+                init_key: KeySlice,
+                hint: usize,
+            ) -> Result<usize, usize> {
+                let key_as_slice = init_key.as_ref();
+                let key_of = self.key_fn();
+                let idx = $crate::merge::partition_point_from(&self.$field, hint, |probe| {
+                    Self::cmp_keys(&key_of(probe), &key_as_slice) == std::cmp::Ordering::Less
+                });
+                match self.$field.get(idx) {
+                    Some(probe) if key_of(probe) == key_as_slice => Ok(idx),
+                    _ => Err(idx),
+                }
+            }
+
+            #[inline]
+            fn compare(slice: &[$elem], other: &[$elem]) -> (usize, std::cmp::Ordering) {
+                let l = std::cmp::min(slice.len(), other.len());
+                let mut prefix_len = 0;
+
+                // Slice to the loop iteration range to enable bound check
+                // elimination in the compiler
+                let lhs = &slice[..l];
+                let rhs = &other[..l];
+
+                for i in 0..l {
+                    match lhs[i].cmp(&rhs[i]) {
+                        std::cmp::Ordering::Equal => { prefix_len += 1 }
+                        non_eq => return (prefix_len, non_eq),
+                    }
+                }
+
+                (prefix_len, slice.len().cmp(&other.len()))
+            }
+
+            /// Resolves a batch of keys at once, in the order of the keys. The keys are searched
+            /// for in groups of 8, whose binary searches run in lockstep to hide memory latency.
+            /// Every search in a group starts on the side of the result for the last key of the
+            /// previous group where its key lies, so that sorted keys search less of the
+            /// collection.
+            ///
+            /// When several elements have a key, the index of the first is returned, like
+            /// `position_from_hint` does. `position` only guarantees this on `stable` collections,
+            /// but both always agree on whether a key is present.
+            pub fn positions_many<KeySlice: AsRef<[$elem]>>(
+                &self,
+                keys: &[KeySlice],
+            ) -> Vec<Result<usize, usize>> {
+                let key_of = self.key_fn();
+                let bounds = self.lower_bounds(keys.len(), |probe, query| {
+                    Self::cmp_keys(&key_of(probe), &keys[query].as_ref())
+                        == std::cmp::Ordering::Less
+                });
+                bounds
+                    .into_iter()
+                    .zip(keys)
+                    .map(|(idx, key)| match self.$field.get(idx) {
+                        Some(probe) if key_of(probe) == key.as_ref() => Ok(idx),
+                        _ => Err(idx),
+                    })
+                    .collect()
+            }
+
+            /// Finds the elements with the given keys, in the order of the keys, like
+            /// `positions_many`.
+            pub fn find_many<KeySlice: AsRef<[$elem]>>(&self, keys: &[KeySlice]) -> Vec<Option<&$val>> {
+                self.positions_many(keys)
+                    .into_iter()
+                    .map(|res| res.ok().map(|idx| &self.$field[idx]))
+                    .collect()
+            }
+
+            /// Finds and returns reference to element with given key, if it exists.
+            /// Implementation largely taken from `::std::vec::Vec::binary_search_by`.
+            pub fn find<KeySlice: AsRef<[$elem]>>(&self, init_key: KeySlice) -> Option<&$val> {
+                // The unsafe block is OK because `position` is guaranteed to
+                // return a valid index.
+                self.position(init_key).ok().map(|ix| unsafe { self.$field.get_unchecked(ix) })
+            }
+
+            /// Checks whether there is a value with that key in the collection. This is
+            /// done in `O(log(n))` time.
+            pub fn contains<KeySlice: AsRef<[$elem]>>(&self, key: KeySlice) -> bool {
+                self.position(key).is_ok()
+            }
+
+            /// Returns a cursor positioned before the value with the given key, or before the
+            /// position where it would be inserted when it is not present.
+            pub fn cursor_at<KeySlice: AsRef<[$elem]>>(
+                &self,
+                key: KeySlice,
+            ) -> $crate::cursor::Cursor<'_, $val> {
+                match self.position(key) {
+                    Ok(idx) | Err(idx) => $crate::cursor::Cursor::new(&self.$field, idx),
+                }
+            }
+
+            /// Returns a cursor like `cursor_at` that can also remove and insert values.
+            pub fn cursor_mut_at<KeySlice: AsRef<[$elem]>>(
+                &mut self,
+                key: KeySlice,
+            ) -> $crate::cursor::CursorMut<'_, Self> {
+                match self.position(key) {
+                    Ok(idx) | Err(idx) => $crate::cursor::CursorMut::new(self, idx),
+                }
+            }
+
+            /// Applies `f` to a value with the given key, if any, and moves it to the position
+            /// of its new key like `update_key_at`. Returns the new index of the value, as
+            /// `update_key_at` does.
+            pub fn rekey<KeySlice: AsRef<[$elem]>>(
+                &mut self,
+                key: KeySlice,
+                f: impl FnOnce(&mut $val),
+            ) -> Option<usize> {
+                let idx = self.position(key).ok()?;
+                Some(self.update_key_at(idx, f))
+            }
+
+            /// Removes and returns a single value from the collection with the given key,
+            /// if it exists. This operation has linear worst-case time complexity.
+            pub fn remove<KeySlice: AsRef<[$elem]>>(&mut self, key: KeySlice) -> Option<$val> {
+                self.position(key).ok().map(|idx| {
+                    let val = self.$field.remove(idx);
+                    self.index_removed(idx);
+                    val
+                })
+            }
+        }
+
+        impl<$($gen)*> $crate::frozen::FrozenLookup<[$elem]> for $name<$($args)*> where $($bounds)* {
+            fn cmp_key(&self, val: &$val, key: &[$elem]) -> std::cmp::Ordering {
+                Self::cmp_keys(&self.key_fn()(val), &key)
+            }
+        }
+
+        $crate::sortedvec! {
+            @collection $name [$($gen)*] [$($args)*] [$($bounds)*] $val, [$elem], $field
+        }
+    };
+(
+    @search binary $name:ident [$($gen:tt)*] [$($args:tt)*] [$($bounds:tt)*]
+    $val:ty, $key:ty, $field:tt
+) => {
+        impl<$($gen)*> $name<$($args)*> where $($bounds)* {
+            // private method
+            fn search_position(&self, key: &$key) -> Result<usize, usize> {
+                let key_of = self.key_fn();
+                if Self::STABLE {
+                    let idx = self.$field.partition_point(|probe| {
+                        Self::cmp_keys(&key_of(probe), key) == std::cmp::Ordering::Less
+                    });
+                    match self.$field.get(idx) {
+                        Some(probe) if key_of(probe) == *key => Ok(idx),
+                        _ => Err(idx),
+                    }
+                } else {
+                    self.$field
+                        .binary_search_by(|probe| Self::cmp_keys(&key_of(probe), key))
+                }
+            }
+        }
+    };
+(
+    @search branchless $name:ident [$($gen:tt)*] [$($args:tt)*] [$($bounds:tt)*]
+    $val:ty, $key:ty, $field:tt
+) => {
+        impl<$($gen)*> $name<$($args)*> where $($bounds)* {
+            // private method
+            fn search_position(&self, key: &$key) -> Result<usize, usize> {
+                let key_of = self.key_fn();
+                let idx = $crate::merge::lower_bound(&self.$field, |probe| {
+                    Self::cmp_keys(&key_of(probe), key) == std::cmp::Ordering::Less
+                });
+                match self.$field.get(idx) {
+                    Some(probe) if key_of(probe) == *key => Ok(idx),
+                    _ => Err(idx),
+                }
+            }
+        }
+    };
+(
+    @search interpolation $name:ident [$($gen:tt)*] [$($args:tt)*] [$($bounds:tt)*]
+    $val:ty, $key:ty, $field:tt
+) => {
+        impl<$($gen)*> $name<$($args)*> where $($bounds)* {
+            // private method
+            fn search_position(&self, key: &$key) -> Result<usize, usize> {
+                let key_of = self.key_fn();
+                // Descending keys are mapped to descending numbers, so that the numbers of the
+                // values increase in the order of the collection either way.
+                let num = |key: &$key| {
+                    let num = $crate::InterpolationKey::to_u64(key);
+                    if Self::DESCENDING {
+                        !num
+                    } else {
+                        num
+                    }
+                };
+                let idx = $crate::merge::interpolation_search(
+                    &self.$field,
+                    num(key),
+                    |probe| num(&key_of(probe)),
+                    |probe| Self::cmp_keys(&key_of(probe), key) == std::cmp::Ordering::Less,
+                );
+                match self.$field.get(idx) {
+                    Some(probe) if key_of(probe) == *key => Ok(idx),
+                    _ => Err(idx),
+                }
+            }
+        }
+    };
+(
+    @search $strategy:ident $($rest:tt)*
+) => {
+        compile_error!(concat!("unknown sortedvec search strategy: ", stringify!($strategy)));
+    };
+(
+    @search_slice binary $name:ident [$($gen:tt)*] [$($args:tt)*] [$($bounds:tt)*]
+    $val:ty, $elem:ty, $field:tt
+) => {
+        impl<$($gen)*> $name<$($args)*> where $($bounds)* {
+            // private method
+            fn search_position(&self, key_as_slice: &[$elem]) -> Result<usize, usize> {
+                let key_of = self.key_fn();
+                if Self::STABLE {
+                    let idx = self.$field.partition_point(|probe| {
+                        Self::cmp_keys(&key_of(probe), &key_as_slice) == std::cmp::Ordering::Less
+                    });
+                    return match self.$field.get(idx) {
+                        Some(probe) if key_of(probe) == key_as_slice => Ok(idx),
+                        _ => Err(idx),
+                    };
+                }
+                let mut size = self.$field.len();
+                let mut upper_shared_prefix = 0;
+                let mut lower_shared_prefix = 0;
+                if size == 0 {
+                    return Err(0);
+                }
+                let mut base = 0usize;
+                while size > 1 {
+                    let half = size / 2;
+                    let mid = base + half;
+                    let prefix_skip = std::cmp::min(lower_shared_prefix, upper_shared_prefix);
+                    // mid is always in [0, size), that means mid is >= 0 and < size.
+                    // mid >= 0: by definition
+                    // mid < size: mid = size / 2 + size / 4 + size / 8 ...
+                    let elt = unsafe { self.$field.get_unchecked(mid) };
+                    let key = key_of(elt);
+                    let (prefix_len, cmp) = unsafe {
+                        Self::compare(key.get_unchecked(prefix_skip..), key_as_slice.get_unchecked(prefix_skip..))
+                    };
+                    base = match Self::directed(cmp) {
+                        std::cmp::Ordering::Greater => {
+                            upper_shared_prefix = prefix_skip + prefix_len;
+                            base
+                        }
+                        std::cmp::Ordering::Less => {
+                            lower_shared_prefix = prefix_skip + prefix_len;
+                            mid
+                        }
+                        std::cmp::Ordering::Equal => return Ok(mid),
+                    };
+                    size -= half;
+                }
+                let prefix_skip = std::cmp::min(lower_shared_prefix, upper_shared_prefix);
+                // base is always in [0, size) because base <= mid.
+                let elt = unsafe { self.$field.get_unchecked(base) };
+                let key = unsafe { key_of(elt).get_unchecked(prefix_skip..) };
+                let (_prefix, cmp) = unsafe { Self::compare(key, key_as_slice.get_unchecked(prefix_skip..)) };
+                match Self::directed(cmp) {
+                    std::cmp::Ordering::Equal => Ok(base),
+                    std::cmp::Ordering::Less => Err(base + 1),
+                    std::cmp::Ordering::Greater => Err(base),
+                }
+            }
+        }
+    };
+(
+    @search_slice branchless $name:ident [$($gen:tt)*] [$($args:tt)*] [$($bounds:tt)*]
+    $val:ty, $elem:ty, $field:tt
+) => {
+        impl<$($gen)*> $name<$($args)*> where $($bounds)* {
+            // private method
+            fn search_position(&self, key_as_slice: &[$elem]) -> Result<usize, usize> {
+                let key_of = self.key_fn();
+                let vals = &self.$field[..];
+                if vals.is_empty() {
+                    return Err(0);
+                }
+                // Lengths of the prefixes shared with the key by the closest values known to be
+                // ordered before and not before it. The values in between share the shortest of
+                // both prefixes with the key, so comparisons can skip it.
+                let mut lower_shared_prefix = 0;
+                let mut upper_shared_prefix = 0;
+                let mut base = 0usize;
+                let mut size = vals.len();
+                // Like `merge::lower_bound`, this loop picks a half without branching on the
+                // comparison and prefetches both candidate midpoints of the next iteration.
+                while size > 1 {
+                    let half = size / 2;
+                    let mid = base + half;
+                    $crate::merge::prefetch(vals, base + (size - half) / 2);
+                    $crate::merge::prefetch(vals, mid + (size - half) / 2);
+                    let prefix_skip = std::cmp::min(lower_shared_prefix, upper_shared_prefix);
+                    // SAFETY: `base + size <= vals.len()` holds throughout the loop and
+                    // `half < size`, so `mid` is in bounds.
+                    let elt = unsafe { vals.get_unchecked(mid) };
+                    // The key of `elt` only shares the skipped prefix when the values are
+                    // sorted, so it is sliced with a bounds check.
+                    let key = &key_of(elt)[prefix_skip..];
+                    // SAFETY: shared prefixes are never longer than `key_as_slice`.
+                    let rest = unsafe { key_as_slice.get_unchecked(prefix_skip..) };
+                    let (prefix_len, cmp) = Self::compare(key, rest);
+                    let less = Self::directed(cmp) == std::cmp::Ordering::Less;
+                    let shared_prefix = prefix_skip + prefix_len;
+                    base = if less { mid } else { base };
+                    lower_shared_prefix = if less { shared_prefix } else { lower_shared_prefix };
+                    upper_shared_prefix = if less { upper_shared_prefix } else { shared_prefix };
+                    size -= half;
+                }
+                // The first value that is not ordered before the key is either `base` or the
+                // one after it.
+                let less = |idx: usize| {
+                    Self::directed(key_of(&vals[idx]).cmp(key_as_slice)) == std::cmp::Ordering::Less
+                };
+                let idx = base + less(base) as usize;
+                match vals.get(idx) {
+                    Some(probe) if key_of(probe) == key_as_slice => Ok(idx),
+                    _ => Err(idx),
+                }
+            }
+        }
+    };
+(
+    @search_slice interpolation $($rest:tt)*
+) => {
+        compile_error!("interpolation search is not supported on slice keys");
+    };
+(
+    @search_slice $strategy:ident $($rest:tt)*
+) => {
+        compile_error!(concat!("unknown sortedvec_slicekey search strategy: ", stringify!($strategy)));
+    };
+(
+    @index_at $name:ident $gen:tt $args:tt $bounds:tt $field:tt,
+    $($idx:ty => $out:ty),*
+) => {
+        $(
+            $crate::sortedvec! { @index_at_one $name $gen $args $bounds $field, $idx => $out }
+        )*
+    };
+(
+    @index_at_one $name:ident [$($gen:tt)*] [$($args:tt)*] [$($bounds:tt)*] $field:tt,
+    $idx:ty => $out:ty
+) => {
+        impl<$($gen)*> std::ops::Index<$idx> for $name<$($args)*> where $($bounds)* {
+            type Output = $out;
+
+            fn index(&self, idx: $idx) -> &$out {
+                &self.$field[idx]
+            }
+        }
+    };
+(
+    @collection $name:ident [$($gen:tt)*] [$($args:tt)*] [$($bounds:tt)*]
+    $val:ty, $query:ty, $field:tt
+) => {
+        $crate::sortedvec! {
+            @index_at $name [$($gen)*] [$($args)*] [$($bounds)*] $field,
+            usize => $val,
+            std::ops::Range<usize> => [$val],
+            std::ops::RangeFrom<usize> => [$val],
+            std::ops::RangeFull => [$val],
+            std::ops::RangeInclusive<usize> => [$val],
+            std::ops::RangeTo<usize> => [$val],
+            std::ops::RangeToInclusive<usize> => [$val],
+            (std::ops::Bound<usize>, std::ops::Bound<usize>) => [$val]
+        }
+
+        impl<$($gen)*> std::ops::Index<&$query> for $name<$($args)*> where $($bounds)* {
+            type Output = $val;
+
+            /// Returns a reference to the value with the given key.
+            ///
+            /// # Panics
+            ///
+            /// Panics if there is no value with the given key in the collection.
+            fn index(&self, key: &$query) -> &$val {
+                Self::find(self, key).expect("no value found for key")
+            }
+        }
+
+        impl<$($gen)*> $crate::SortedCollection<$query> for $name<$($args)*> where $($bounds)* {
+            type Value = $val;
+
+            fn position(&self, key: &$query) -> Result<usize, usize> {
+                Self::position(self, key)
+            }
+
+            fn find(&self, key: &$query) -> Option<&$val> {
+                Self::find(self, key)
+            }
+
+            fn insert(&mut self, val: $val) {
+                Self::insert(self, val)
+            }
+
+            fn remove(&mut self, key: &$query) -> Option<$val> {
+                Self::remove(self, key)
+            }
+
+            fn len(&self) -> usize {
+                self.$field.len()
+            }
+        }
+    };
+(
+    @extras [$extra:ident $($rest:ident)*] $name:ident $gen:tt $args:tt $bounds:tt $val:ty, $field:tt
+) => {
+        $crate::sortedvec! { @extra $extra $name $gen $args $bounds $val, $field }
+        $crate::sortedvec! { @extras [$($rest)*] $name $gen $args $bounds $val, $field }
+    };
+(
+    @extras [] $name:ident $gen:tt $args:tt $bounds:tt $val:ty, $field:tt
+) => {};
+(
+    @extra partial_eq $name:ident [$($gen:tt)*] [$($args:tt)*] [$($bounds:tt)*] $val:ty, $field:tt
+) => {
+        impl<$($gen)*> PartialEq<[$val]> for $name<$($args)*> where $($bounds)* $val: PartialEq {
+            fn eq(&self, other: &[$val]) -> bool {
+                self.$field[..] == *other
+            }
+        }
+
+        impl<$($gen)*> PartialEq<Vec<$val>> for $name<$($args)*> where $($bounds)* $val: PartialEq {
+            fn eq(&self, other: &Vec<$val>) -> bool {
+                self.$field == *other
+            }
+        }
+
+        impl<$($gen)*> PartialEq<$name<$($args)*>> for Vec<$val> where $($bounds)* $val: PartialEq {
+            fn eq(&self, other: &$name<$($args)*>) -> bool {
+                *self == other.$field
+            }
+        }
+
+        #[allow(dead_code)]
+        impl<$($gen)*> $name<$($args)*> where $($bounds)* $val: PartialEq {
+            /// Returns an iterator over the changes that turn `self` into `new`, in order. Like
+            /// `diff_by`, using `PartialEq` to compare values with the same key.
+            pub fn diff<'sorted>(
+                &'sorted self,
+                new: &'sorted Self,
+            ) -> impl Iterator<Item = $crate::diff::Change<&'sorted $val>> + 'sorted {
+                self.diff_by(new, PartialEq::eq)
+            }
+
+            /// Applies a sequence of changes, like the one returned by `diff` on cloned values, in
+            /// a single pass. Like `apply_patch_by`, using `PartialEq` to match removed and
+            /// changed values.
+            ///
+            /// # Panics
+            ///
+            /// Panics if the changes are not ordered by key, in the order of the collection.
+            pub fn apply_patch(
+                &mut self,
+                patch: impl IntoIterator<Item = $crate::diff::Change<$val>>,
+            ) {
+                self.apply_patch_by(patch, PartialEq::eq)
+            }
+        }
+    };
+(
+    @extra debug_map $name:ident [$($gen:tt)*] [$($args:tt)*] [$($bounds:tt)*] $val:ty, $field:tt
+) => {
+        impl<$($gen)*> std::fmt::Debug for $name<$($args)*>
+        where
+            $($bounds)*
+            $val: std::fmt::Debug,
+        {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                let key_of = self.key_fn();
+                f.debug_map()
+                    .entries(self.$field.iter().map(|val| (key_of(val), val)))
+                    .finish()
+            }
+        }
+    };
+(
+    @unique []
+) => {
+        false
+    };
+(
+    @unique [$($policy:tt)+]
+) => {
+        true
+    };
+(
+    @merge_duplicate $kept:ident $dup:ident [$(last)?]
+) => {
+        *$kept = $dup
+    };
+(
+    @merge_duplicate $kept:ident $dup:ident [first]
+) => {{
+        let _ = ($kept, $dup);
+    }};
+(
+    @merge_duplicate $kept:ident $dup:ident [merge $path:path]
+) => {
+        $path($kept, $dup)
+    };
+(
+    @impl $name:ident [$($gen:tt)*] [$($args:tt)*] [$($bounds:tt)*],
+    $val:ty, $key:ty, $ctx:ty, $keyfn:ty, $derive:ident, $field:tt, $search:tt,
+    [$unique:tt $stable:tt $descending:tt $extras:tt $strategy:ident]
+) => {
+        #[allow(dead_code)]
+        impl<$($gen)*> $name<$($args)*> where $($bounds)* {
+            const UNIQUE: bool = $crate::sortedvec!(@unique $unique);
+            const STABLE: bool = $stable;
+            const DESCENDING: bool = $descending;
+
+            // private method
+            fn first_unsorted(
+                vals: &[$val],
+                key_of: impl Fn(&$val) -> Option<$key>,
+            ) -> Option<usize> {
+                let mut prev = None;
+                let keys = vals
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(idx, val)| key_of(val).map(|key| (idx, key)));
+                for (idx, key) in keys {
+                    if let Some(prev) = &prev {
+                        match Self::cmp_keys(prev, &key) {
+                            std::cmp::Ordering::Greater => return Some(idx),
+                            std::cmp::Ordering::Equal if Self::UNIQUE => return Some(idx),
+                            _ => {}
+                        }
+                    }
+                    prev = Some(key);
+                }
+                None
+            }
+
+            /// Checks that the values are sorted on their keys, without duplicate keys when the
+            /// collection is `unique`, and that deriving the key of every value twice gives the
+            /// same key. This takes linear time.
+            ///
+            /// These checks run after every operation that may break the order of the values in
+            /// builds with debug assertions, as well as in release builds when the `validate`
+            /// feature is enabled. They panic when the invariants do not hold. Without the
+            /// `validate` feature, operations that insert or move a single value only check it
+            /// against its neighbours, so that they keep their complexity.
+            pub fn validate(&self) -> Result<(), $crate::InvariantViolation> {
+                self.validate_range(0, self.$field.len())
+            }
+
+            // private method
+            fn validate_range(
+                &self,
+                start: usize,
+                end: usize,
+            ) -> Result<(), $crate::InvariantViolation> {
+                let key_of = self.key_fn();
+                let vals = &self.$field[start..end];
+                let non_deterministic = vals.iter().position(|val| {
+                    let (first, second) = (key_of(val), key_of(val));
+                    first != second
+                });
+                if let Some(index) = non_deterministic {
+                    return Err($crate::InvariantViolation::NonDeterministicKey(start + index));
+                }
+                match Self::first_unsorted(vals, |val| Some(key_of(val))) {
+                    Some(index) => Err($crate::InvariantViolation::NotSorted(start + index)),
+                    None => Ok(()),
+                }
+            }
+
+            // private method
+            fn check_invariants(&self) {
+                self.check_invariants_between(0, self.$field.len());
+            }
+
+            // private method
+            fn check_invariants_at(&self, index: usize) {
+                if $crate::VALIDATE {
+                    self.check_invariants();
+                } else {
+                    let end = self.$field.len().min(index + 2);
+                    self.check_invariants_between(index.saturating_sub(1), end);
+                }
+            }
+
+            // private method
+            fn check_invariants_between(&self, start: usize, end: usize) {
+                if cfg!(debug_assertions) || $crate::VALIDATE {
+                    if let Err(err) = self.validate_range(start, end) {
+                        panic!("sorted vector invariant violated: {}", err);
+                    }
+                }
+            }
+
+            /// Returns the values of the collection, in order.
+            pub fn as_slice(&self) -> &[$val] {
+                &self.$field
+            }
+
+            /// Consumes the collection and returns its values, like converting it into a `Vec`
+            /// using `From`.
+            pub fn into_inner(self) -> Vec<$val> {
+                self.into_vec()
+            }
+
+            /// Turns the collection into an immutable [`Frozen`] collection, which stores its
+            /// values in a layout that makes lookups faster for large collections. This takes
+            /// linear time.
+            ///
+            /// [`Frozen`]: frozen/struct.Frozen.html
+            pub fn freeze(self) -> $crate::frozen::Frozen<Self> {
+                $crate::frozen::Frozen::new(self)
+            }
+
+            // private method
+            fn merge_duplicate(kept: &mut $val, dup: $val) {
+                $crate::sortedvec!(@merge_duplicate kept dup $unique)
+            }
+
+            // private method
+            fn fold_runs(
+                inner: &mut Vec<$val>,
+                key_of: impl Fn(&$val) -> $key,
+                mut merge: impl FnMut(&mut $val, $val),
+            ) {
+                let vals = std::mem::replace(inner, Vec::with_capacity(inner.len()));
+                for val in vals {
+                    match inner.last_mut() {
+                        Some(kept) if key_of(kept) == key_of(&val) => merge(kept, val),
+                        _ => inner.push(val),
+                    }
+                    Self::assert_sorted_at(inner, &key_of, inner.len() - 1);
+                }
+            }
+
+            // private method
+            fn assert_sorted_at(vals: &[$val], key_of: impl Fn(&$val) -> $key, index: usize) {
+                // Merges may change keys, so this is checked in every build: unsorted values
+                // would break the lookups.
+                for i in index.max(1)..vals.len().min(index + 2) {
+                    let ord = Self::cmp_keys(&key_of(&vals[i - 1]), &key_of(&vals[i]));
+                    if ord == std::cmp::Ordering::Greater {
+                        panic!(
+                            "sorted vector invariant violated: {}",
+                            $crate::InvariantViolation::NotSorted(i)
+                        );
+                    }
+                }
+            }
+
+            // private method
+            fn directed(ord: std::cmp::Ordering) -> std::cmp::Ordering {
+                if Self::DESCENDING {
+                    ord.reverse()
+                } else {
+                    ord
+                }
+            }
+
+            // private method
+            fn cmp_keys(lhs: &$key, rhs: &$key) -> std::cmp::Ordering {
+                Self::directed(lhs.cmp(rhs))
+            }
+
+            // private method
+            fn lower_bounds(&self, queries: usize, is_less: impl Fn(&$val, usize) -> bool) -> Vec<usize> {
+                // Number of searches that run in lockstep, so that their memory accesses overlap.
+                const LANES: usize = 8;
+                let vals = &self.$field[..];
+                let mut bounds = Vec::with_capacity(queries);
+                // The lower bound of every lane lies in `base..=base + size`.
+                let mut windows = [(0usize, 0usize); LANES];
+                let mut pivot = 0;
+                for start in (0..queries).step_by(LANES) {
+                    let lanes = std::cmp::min(LANES, queries - start);
+                    // Narrow down every window to one side of the previous lower bound.
+                    for (lane, window) in windows[..lanes].iter_mut().enumerate() {
+                        *window = match vals.get(pivot) {
+                            Some(probe) if is_less(probe, start + lane) => {
+                                (pivot + 1, vals.len() - pivot - 1)
+                            }
+                            _ => (0, pivot),
+                        };
+                    }
+                    let mut searching = true;
+                    while searching {
+                        searching = false;
+                        for (lane, (base, size)) in windows[..lanes].iter_mut().enumerate() {
+                            if *size > 0 {
+                                let half = *size / 2;
+                                let mid = *base + half;
+                                if is_less(&vals[mid], start + lane) {
+                                    *base = mid + 1;
+                                    *size -= half + 1;
+                                } else {
+                                    *size = half;
+                                }
+                                searching = true;
+                            }
+                        }
+                    }
+                    bounds.extend(windows[..lanes].iter().map(|&(base, _)| base));
+                    pivot = bounds[bounds.len() - 1];
+                }
+                bounds
+            }
+
+            /// Finds the element whose key is closest to the given key, provided that its
+            /// distance to that key does not exceed `epsilon`. This is useful for keys that
+            /// wrap floating point numbers, like [`NanLast`]. It has logarithmic worst case
+            /// time complexity.
+            ///
+            /// [`NanLast`]: float/struct.NanLast.html
+            pub fn find_within<QueryKey>(
+                &self,
+                key: &QueryKey,
+                epsilon: QueryKey::Float,
+            ) -> Option<&$val>
+            where
+                QueryKey: $crate::float::FloatKey,
+                $keyfn: $crate::DeriveKey<$ctx, $val, QueryKey>,
+            {
+                let derive: $keyfn = Self::$derive;
+                let context = self.key_context();
+                let idx = self.$field.partition_point(|probe| {
+                    let ord = $crate::DeriveKey::derive(derive, context, probe).cmp(key);
+                    Self::directed(ord) == std::cmp::Ordering::Less
+                });
+                let distance = |val: &$val| {
+                    $crate::DeriveKey::derive(derive, context, val).distance(key)
+                };
+                let within = |val: &&$val| distance(val) <= epsilon;
+                let below = idx.checked_sub(1).map(|i| &self.$field[i]).filter(within);
+                let above = self.$field.get(idx).filter(within);
+                match (below, above) {
+                    (Some(lo), Some(hi)) if distance(hi) < distance(lo) => Some(hi),
+                    (lo, hi) => lo.or(hi),
+                }
+            }
+
+            /// Inserts a new value into the collection, maintaining the internal
+            /// order invariant. This is an `O(n)` operation.
+            ///
+            /// When the collection is `unique`, the value with the same key, if any, is
+            /// replaced, kept or merged with the new value according to the policy. When it is
+            /// `stable`, the value is placed after all values with the same key.
+            pub fn insert(&mut self, val: $val) {
+                let mut val = match self.route_unkeyed(val) {
+                    Some(val) => val,
+                    None => return,
+                };
+                if Self::UNIQUE {
+                    let existing = self.position(&self.key_fn()(&val));
+                    if let Ok(idx) = existing {
+                        let mut kept = self.$field.remove(idx);
+                        self.index_removed(idx);
+                        Self::merge_duplicate(&mut kept, val);
+                        val = kept;
+                    }
+                }
+                let key = &self.key_fn()(&val);
+                let idx = if Self::STABLE {
+                    let key_of = self.key_fn();
+                    self.$field.partition_point(|probe| {
+                        Self::cmp_keys(&key_of(probe), key) != std::cmp::Ordering::Greater
+                    })
+                } else {
+                    match self.position(key) {
+                        Ok(i) | Err(i) => i,
+                    }
+                };
+                self.$field.insert(idx, val);
+                self.index_inserted(idx);
+                self.check_invariants_at(idx);
+            }
+
+            /// Applies `f` to the value at `index`, which may change its key, and moves the value
+            /// to its new position by rotating the values in between. It is placed after the
+            /// values with an equal key. Returns the new index of the value. This takes time
+            /// linear in the distance the value moves.
+            ///
+            /// When the collection is `unique` and the new key is already present, the value is
+            /// combined with the existing one according to the same policy as `insert`, and the
+            /// index of the combined value is returned. When the key derivation function is
+            /// `#[optional]` and the value no longer has a key, it is moved to the values without
+            /// a key and the new length of the collection is returned.
+            ///
+            /// # Panics
+            ///
+            /// Panics if `index` is out of bounds.
+            pub fn update_key_at(&mut self, index: usize, f: impl FnOnce(&mut $val)) -> usize {
+                f(&mut self.$field[index]);
+                if self.unkey_at(index) {
+                    self.index_removed(index);
+                    return self.$field.len();
+                }
+                let (target, collision) = {
+                    let (inner, key_of) = self.inner_and_key_fn();
+                    let key = key_of(&inner[index]);
+                    let before = |probe: &$val| {
+                        Self::cmp_keys(&key_of(probe), &key) != std::cmp::Ordering::Greater
+                    };
+                    let target = inner[..index].partition_point(&before)
+                        + inner[index + 1..].partition_point(&before);
+                    let collision = Self::UNIQUE && target > 0 && {
+                        let neighbour = if target - 1 < index { target - 1 } else { target };
+                        key_of(&inner[neighbour]) == key
+                    };
+                    (target, collision)
+                };
+
+                if target < index {
+                    self.$field[target..=index].rotate_right(1);
+                } else {
+                    self.$field[index..=target].rotate_left(1);
+                }
+
+                if collision {
+                    let dup = self.$field.remove(target);
+                    Self::merge_duplicate(&mut self.$field[target - 1], dup);
+                    let (inner, key_of) = self.inner_and_key_fn();
+                    Self::assert_sorted_at(inner, key_of, target - 1);
+                    self.reindex();
+                    self.check_invariants_at(target - 1);
+                    return target - 1;
+                }
+
+                self.index_removed(index);
+                self.index_inserted(target);
+                self.check_invariants_at(target);
+                target
+            }
+
+            /// Splits the collection into two at the given index.
+            ///
+            /// Returns a newly allocated `Self`. `self` contains elements `[0, at)`,
+            /// and the returned `Self` contains elements `[at, len)`.
+            ///
+            /// Note that the capacity of `self` does not change.
+            ///
+            /// # Panics
+            ///
+            /// Panics if `at > len`.
+            pub fn split_off(&mut self, at: usize) -> Self {
+                let other_inner = self.$field.split_off(at);
+                self.index_truncated(at);
+                self.with_inner(other_inner)
+            }
+
+            /// Removes all elements but the first of every run of elements that resolve to the
+            /// same key.
+            pub fn dedup(&mut self) {
+                {
+                    let (inner, key_of) = self.inner_and_key_fn();
+                    inner.dedup_by(|a, b| key_of(a) == key_of(b));
+                }
+                self.reindex();
+            }
+
+            /// Removes all elements but the last of every run of elements that resolve to the
+            /// same key.
+            pub fn dedup_keep_last(&mut self) {
+                self.dedup_by_merge(|kept, dup| *kept = dup);
+            }
+
+            /// Merges every run of elements that resolve to the same key into its first element,
+            /// by calling `merge` with that element and each of the others in order. `merge`
+            /// must not change the key of the kept element, and this panics if it moves the
+            /// element out of order.
+            ///
+            /// ```rust
+            /// use sortedvec::sortedvec;
+            ///
+            /// sortedvec! {
+            ///     struct Counts {
+            ///         fn derive_key(count: &(&'static str, u32)) -> &'static str { count.0 }
+            ///     }
+            /// }
+            ///
+            /// let mut counts = Counts::from(vec![("b", 1), ("a", 2), ("b", 3), ("a", 4)]);
+            /// counts.dedup_by_merge(|kept, dup| kept.1 += dup.1);
+            ///
+            /// assert_eq!(&[("a", 6), ("b", 4)], &counts[..]);
+            /// ```
+            pub fn dedup_by_merge(&mut self, merge: impl FnMut(&mut $val, $val)) {
+                {
+                    let (inner, key_of) = self.inner_and_key_fn();
+                    Self::fold_runs(inner, key_of, merge);
+                }
+                self.reindex();
+                self.check_invariants();
+            }
+
+            // private method
+            fn value_cmp(&self) -> impl Fn(&$val, &$val) -> std::cmp::Ordering + '_ {
+                let key_of = self.key_fn();
+                move |a: &$val, b: &$val| Self::cmp_keys(&key_of(a), &key_of(b))
+            }
+
+            // private method
+            fn set_op<'sorted>(
+                &'sorted self,
+                other: &'sorted Self,
+                op: $crate::merge::Op,
+            ) -> impl Iterator<Item = &'sorted $val> + 'sorted {
+                let key_of = self.key_fn();
+                debug_assert!(
+                    Self::first_unsorted(&other.$field, |val| Some(key_of(val))).is_none(),
+                    "the other collection is not sorted in the order of this one"
+                );
+                $crate::merge::SetOp::new(&self.$field, &other.$field, self.value_cmp(), op)
+            }
+
+            // private method
+            fn into_set_op(mut self, other: Self, op: $crate::merge::Op) -> Self {
+                let mut other = self.adopt_context(other);
+                {
+                    let (inner, key_of) = self.inner_and_key_fn();
+                    let lhs = std::mem::take(inner);
+                    let rhs = std::mem::take(&mut other.$field);
+                    let cmp = |a: &$val, b: &$val| Self::cmp_keys(&key_of(a), &key_of(b));
+                    *inner = $crate::merge::set_op_owned(lhs, rhs, cmp, op);
+                }
+                self.reindex();
+                self.set_op_unkeyed(&mut other, op);
+                self.check_invariants();
+                self
+            }
+
+            /// Returns an iterator over the values in either collection, in order. Values with
+            /// equal keys in both collections are paired up in order, and only the value from
+            /// `self` is yielded for every pair. This takes linear time.
+            pub fn union<'sorted>(
+                &'sorted self,
+                other: &'sorted Self,
+            ) -> impl Iterator<Item = &'sorted $val> + 'sorted {
+                self.set_op(other, $crate::merge::Op::Union)
+            }
+
+            /// Returns an iterator over the values of `self` that can be paired with a value with
+            /// an equal key in `other`, in order. Runs of unpaired values are skipped using an
+            /// exponential search, so that this takes `O(m log(n / m))` time, where `m` is the
+            /// length of the smaller collection.
+            pub fn intersection<'sorted>(
+                &'sorted self,
+                other: &'sorted Self,
+            ) -> impl Iterator<Item = &'sorted $val> + 'sorted {
+                self.set_op(other, $crate::merge::Op::Intersection)
+            }
+
+            /// Returns an iterator over the values of `self` that cannot be paired with a value
+            /// with an equal key in `other`, in order. This takes linear time.
+            pub fn difference<'sorted>(
+                &'sorted self,
+                other: &'sorted Self,
+            ) -> impl Iterator<Item = &'sorted $val> + 'sorted {
+                self.set_op(other, $crate::merge::Op::Difference)
+            }
+
+            /// Returns an iterator over the values in either collection that cannot be paired
+            /// with a value with an equal key in the other, in order. This takes linear time.
+            pub fn symmetric_difference<'sorted>(
+                &'sorted self,
+                other: &'sorted Self,
+            ) -> impl Iterator<Item = &'sorted $val> + 'sorted {
+                self.set_op(other, $crate::merge::Op::SymmetricDifference)
+            }
+
+            /// Consumes both collections and returns their union, like `union`.
+            ///
+            /// Values without a key, kept by collections with an `#[optional]` key derivation
+            /// function, cannot be paired up. Like unpaired values with a key, those of `self` are
+            /// kept by all consuming set operations but `into_intersection`, and those of `other`
+            /// are kept by `into_union` and `into_symmetric_difference`, after those of `self`.
+            pub fn into_union(self, other: Self) -> Self {
+                self.into_set_op(other, $crate::merge::Op::Union)
+            }
+
+            /// Consumes both collections and returns their intersection, like `intersection`. See
+            /// `into_union` for values without a key.
+            pub fn into_intersection(self, other: Self) -> Self {
+                self.into_set_op(other, $crate::merge::Op::Intersection)
+            }
+
+            /// Consumes both collections and returns their difference, like `difference`. See
+            /// `into_union` for values without a key.
+            pub fn into_difference(self, other: Self) -> Self {
+                self.into_set_op(other, $crate::merge::Op::Difference)
+            }
+
+            /// Consumes both collections and returns their symmetric difference, like
+            /// `symmetric_difference`. See `into_union` for values without a key.
+            pub fn into_symmetric_difference(self, other: Self) -> Self {
+                self.into_set_op(other, $crate::merge::Op::SymmetricDifference)
+            }
+
+            /// Returns `true` if every value of `self` can be paired with a value with an equal
+            /// key in `other`.
+            pub fn is_subset(&self, other: &Self) -> bool {
+                self.difference(other).next().is_none()
+            }
+
+            /// Returns `true` if no value of `self` has the same key as a value in `other`.
+            pub fn is_disjoint(&self, other: &Self) -> bool {
+                self.intersection(other).next().is_none()
+            }
+
+            // private method
+            fn join<'sorted, Other>(
+                &'sorted self,
+                other: &'sorted [Other],
+                other_key: impl Fn(&Other) -> $key + 'sorted,
+                kind: $crate::merge::JoinKind,
+            ) -> impl Iterator<Item = (&'sorted [$val], &'sorted [Other])> + 'sorted {
+                debug_assert!(
+                    other.windows(2).all(|w| {
+                        Self::cmp_keys(&other_key(&w[0]), &other_key(&w[1]))
+                            != std::cmp::Ordering::Greater
+                    }),
+                    "the values to join with are not sorted in the order of the collection"
+                );
+                $crate::merge::Join::new(
+                    &self.$field,
+                    self.key_fn(),
+                    other,
+                    other_key,
+                    Self::cmp_keys,
+                    kind,
+                )
+            }
+
+            /// Joins this collection with the values of another, which must be sorted in the same
+            /// order on keys of the same type given by `other_key`. Returns an iterator over the
+            /// groups of values with equal keys in both, in order. Every group contains all the
+            /// values with that key, so duplicate keys on either side yield a single pair of
+            /// groups. Runs of unmatched values are skipped using an exponential search.
+            ///
+            /// The order of `other` includes its direction: a collection with `order = descending`
+            /// can only be joined with values sorted in descending order, and the other way
+            /// around. This is checked in debug builds only; joining with values in another order
+            /// yields unspecified groups.
+            ///
+            /// ```rust
+            /// use sortedvec::sortedvec;
+            ///
+            /// sortedvec! {
+            ///     struct Users {
+            ///         fn derive_key(user: &(u64, &'static str)) -> u64 { user.0 }
+            ///     }
+            /// }
+            ///
+            /// sortedvec! {
+            ///     struct Orders {
+            ///         fn derive_key(order: &(u64, u32)) -> u64 { order.0 }
+            ///     }
+            /// }
+            ///
+            /// let users = Users::from(vec![(1, "alice"), (2, "bob"), (3, "carol")]);
+            /// let orders = Orders::from(vec![(3, 20), (1, 10), (3, 30), (4, 40)]);
+            /// let order_key = |order: &(u64, u32)| order.0;
+            ///
+            /// let joined: Vec<_> = users.inner_join(&orders, order_key).collect();
+            /// assert_eq!(
+            ///     vec![
+            ///         (&[(1, "alice")][..], &[(1, 10)][..]),
+            ///         (&[(3, "carol")][..], &[(3, 20), (3, 30)][..]),
+            ///     ],
+            ///     joined
+            /// );
+            ///
+            /// let without_orders: Vec<_> = users.anti_join(&orders, order_key).collect();
+            /// assert_eq!(vec![&[(2, "bob")][..]], without_orders);
+            /// ```
+            pub fn inner_join<'sorted, Other>(
+                &'sorted self,
+                other: &'sorted [Other],
+                other_key: impl Fn(&Other) -> $key + 'sorted,
+            ) -> impl Iterator<Item = (&'sorted [$val], &'sorted [Other])> + 'sorted {
+                self.join(other, other_key, $crate::merge::JoinKind::Inner)
+            }
+
+            /// Like `inner_join`, but also yields the groups of values of `self` without matching
+            /// key in `other`, paired with an empty slice.
+            pub fn left_join<'sorted, Other>(
+                &'sorted self,
+                other: &'sorted [Other],
+                other_key: impl Fn(&Other) -> $key + 'sorted,
+            ) -> impl Iterator<Item = (&'sorted [$val], &'sorted [Other])> + 'sorted {
+                self.join(other, other_key, $crate::merge::JoinKind::LeftOuter)
+            }
+
+            /// Like `inner_join`, but also yields the groups of values on either side without
+            /// matching key on the other side, paired with an empty slice.
+            pub fn full_join<'sorted, Other>(
+                &'sorted self,
+                other: &'sorted [Other],
+                other_key: impl Fn(&Other) -> $key + 'sorted,
+            ) -> impl Iterator<Item = (&'sorted [$val], &'sorted [Other])> + 'sorted {
+                self.join(other, other_key, $crate::merge::JoinKind::FullOuter)
+            }
+
+            /// Returns an iterator over the groups of values of `self` with equal keys for which
+            /// there is a value with that key in `other`, in order. See `inner_join`.
+            pub fn semi_join<'sorted, Other>(
+                &'sorted self,
+                other: &'sorted [Other],
+                other_key: impl Fn(&Other) -> $key + 'sorted,
+            ) -> impl Iterator<Item = &'sorted [$val]> + 'sorted {
+                self.inner_join(other, other_key).map(|(group, _)| group)
+            }
+
+            /// Returns an iterator over the groups of values of `self` with equal keys for which
+            /// there is no value with that key in `other`, in order. See `inner_join`.
+            pub fn anti_join<'sorted, Other>(
+                &'sorted self,
+                other: &'sorted [Other],
+                other_key: impl Fn(&Other) -> $key + 'sorted,
+            ) -> impl Iterator<Item = &'sorted [$val]> + 'sorted {
+                self.left_join(other, other_key)
+                    .filter(|(_, matches)| matches.is_empty())
+                    .map(|(group, _)| group)
+            }
+
+            /// Returns an iterator over the changes that turn `self` into `new`, in order, using
+            /// `eq` to tell whether two values with the same key are equal. See [`Change`] for
+            /// how duplicate keys are handled. This takes linear time.
+            ///
+            /// [`Change`]: diff/enum.Change.html
+            pub fn diff_by<'sorted>(
+                &'sorted self,
+                new: &'sorted Self,
+                eq: impl Fn(&$val, &$val) -> bool + 'sorted,
+            ) -> impl Iterator<Item = $crate::diff::Change<&'sorted $val>> + 'sorted {
+                self.join(&new.$field, self.key_fn(), $crate::merge::JoinKind::FullOuter)
+                    .flat_map(move |(old, new)| {
+                        let unchanged = $crate::merge::groups_eq(old, new, &eq);
+                        $crate::merge::diff_groups(old, new, unchanged)
+                    })
+            }
+
+            /// Applies a sequence of changes, like the one returned by `diff_by` on cloned values,
+            /// in a single pass. Removed and changed values are matched using `eq` among the
+            /// values with the same key, and removals of values that are not present are
+            /// ignored. Changes of values that are not present add the new value. Additions to a
+            /// `unique` collection follow the same policy as `insert`, and values without a key
+            /// are added to the values without a key.
+            ///
+            /// # Panics
+            ///
+            /// Panics if the changes are not ordered by key, in the order of the collection.
+            pub fn apply_patch_by(
+                &mut self,
+                patch: impl IntoIterator<Item = $crate::diff::Change<$val>>,
+                eq: impl Fn(&$val, &$val) -> bool,
+            ) {
+                use std::cmp::Ordering;
+                use $crate::diff::Change;
+
+                let patch: Vec<_> = patch
+                    .into_iter()
+                    .filter_map(|change| self.patch_unkeyed(change, &eq))
+                    .collect();
+                {
+                    let (inner, key_of) = self.inner_and_key_fn();
+                    let mut old = std::mem::take(inner).into_iter().peekable();
+                    // The values with the key of the last change, which later changes may match.
+                    let mut run = Vec::new();
+                    let mut removed = None;
+
+                    for change in patch {
+                        {
+                            let key = key_of(change.value());
+                            let mut prevs = removed.iter().chain(run.last()).chain(inner.last());
+                            let in_order = prevs.all(|prev| {
+                                Self::cmp_keys(&key_of(prev), &key) != Ordering::Greater
+                            });
+                            assert!(in_order, "the changes of the patch are not ordered by key");
+
+                            if run.first().map_or(true, |val| key_of(val) != key) {
+                                inner.append(&mut run);
+                                let cmp = |val: &$val| Self::cmp_keys(&key_of(val), &key);
+                                while let Some(val) = old.next_if(|val| cmp(val).is_lt()) {
+                                    inner.push(val);
+                                }
+                                while let Some(val) = old.next_if(|val| cmp(val).is_eq()) {
+                                    run.push(val);
+                                }
+                            }
+                        }
+                        removed = None;
+                        let (matched, val) = match change {
+                            Change::Added(val) => (None, val),
+                            Change::Removed(val) => {
+                                if let Some(idx) = run.iter().position(|kept| eq(kept, &val)) {
+                                    run.remove(idx);
+                                }
+                                removed = Some(val);
+                                continue;
+                            }
+                            Change::Changed(prev, val) => {
+                                (run.iter().position(|kept| eq(kept, &prev)), val)
+                            }
+                        };
+                        match (matched, run.first_mut()) {
+                            (Some(idx), _) => run[idx] = val,
+                            (None, Some(kept)) if Self::UNIQUE => {
+                                Self::merge_duplicate(kept, val);
+                                let kept = &key_of(kept);
+                                let cmp = |val: &$val| Self::cmp_keys(&key_of(val), kept);
+                                let after_prev = inner.last().map(cmp) != Some(Ordering::Greater);
+                                let before_next = old.peek().map(cmp) != Some(Ordering::Less);
+                                let index = match (after_prev, before_next) {
+                                    (false, _) => Some(inner.len()),
+                                    (true, false) => Some(inner.len() + 1),
+                                    (true, true) => None,
+                                };
+                                if let Some(index) = index {
+                                    panic!(
+                                        "sorted vector invariant violated: {}",
+                                        $crate::InvariantViolation::NotSorted(index)
+                                    );
+                                }
+                            }
+                            (None, _) => run.push(val),
+                        }
+                    }
+
+                    inner.append(&mut run);
+                    inner.extend(old);
+                }
+                self.reindex();
+                self.check_invariants();
+            }
+
+            /// Removes and returns the greatest element with the respect to
+            /// the generated keys, or the smallest when the order is descending.
+            /// An `O(1)` operation.
+            pub fn pop(&mut self) -> Option<$val> {
+                let val = self.$field.pop();
+                self.index_truncated(self.$field.len());
+                val
+            }
+
+            // private method
+            fn sort(&mut self) {
+                self.separate_unkeyed();
+                {
+                    let (inner, key_of) = self.inner_and_key_fn();
+                    let cmp = |a: &$val, b: &$val| {
+                        let lhs = key_of(a);
+                        let rhs = key_of(b);
+                        Self::cmp_keys(&lhs, &rhs)
+                    };
+                    if Self::STABLE || Self::UNIQUE {
+                        inner.sort_by(cmp);
+                    } else {
+                        inner.sort_unstable_by(cmp);
+                    }
+                    if Self::UNIQUE {
+                        Self::fold_runs(inner, &key_of, Self::merge_duplicate);
+                    }
+                }
+                self.reindex();
+                self.check_invariants();
+            }
+        }
+
+        impl<$($gen)*> Extend<$val> for $name<$($args)*> where $($bounds)* {
+            fn extend<Iter>(&mut self, iter: Iter)
+            where
+                Iter: IntoIterator<Item = $val>,
+            {
+                self.$field.extend(iter);
+                self.sort();
+            }
+        }
+
+        impl<$($gen)*> std::iter::IntoIterator for $name<$($args)*> where $($bounds)* {
+            type Item = $val;
+            type IntoIter = std::vec::IntoIter<$val>;
+
+            fn into_iter(self) -> Self::IntoIter {
+                self.into_vec().into_iter()
+            }
+        }
+
+        impl<$($gen)*> From<$name<$($args)*>> for Vec<$val> where $($bounds)* {
+            fn from(sorted: $name<$($args)*>) -> Self {
+                sorted.into_vec()
+            }
+        }
+
+        impl<$($gen)*> $crate::frozen::Freeze for $name<$($args)*> where $($bounds)* {
+            type Value = $val;
+
+            fn values(&self) -> &[$val] {
+                &self.$field
+            }
+
+            unsafe fn values_mut(&mut self) -> &mut Vec<$val> {
+                &mut self.$field
+            }
+
+            fn thawed(&mut self) {
+                self.reindex();
+                self.check_invariants();
+            }
+        }
+
+        impl<$($gen)*> $crate::cursor::CursorTarget for $name<$($args)*> where $($bounds)* {
+            type Value = $val;
+
+            fn values(&self) -> &[$val] {
+                &self.$field
+            }
+
+            fn remove_at(&mut self, idx: usize) -> $val {
+                let val = self.$field.remove(idx);
+                self.index_removed(idx);
+                val
+            }
+
+            fn try_insert_at(&mut self, idx: usize, val: $val) -> Result<bool, $val> {
+                let val = match self.route_unkeyed(val) {
+                    Some(val) => val,
+                    None => return Ok(false),
+                };
+                let fits = {
+                    let key_of = self.key_fn();
+                    let key = key_of(&val);
+                    let ordered = |lhs: &$key, rhs: &$key| match Self::cmp_keys(lhs, rhs) {
+                        std::cmp::Ordering::Less => true,
+                        std::cmp::Ordering::Equal => !Self::UNIQUE,
+                        std::cmp::Ordering::Greater => false,
+                    };
+                    let after_prev = idx
+                        .checked_sub(1)
+                        .map_or(true, |prev| ordered(&key_of(&self.$field[prev]), &key));
+                    let before_next = self.$field
+                        .get(idx)
+                        .map_or(true, |next| ordered(&key, &key_of(next)));
+                    after_prev && before_next
+                };
+                if !fits {
+                    return Err(val);
+                }
+                self.$field.insert(idx, val);
+                self.index_inserted(idx);
+                self.check_invariants_at(idx);
+                Ok(true)
+            }
+        }
+
+        $crate::sortedvec! {
+            @lookup $search $strategy $name [$($gen)*] [$($args)*] [$($bounds)*] $val, $key, $field
+        }
+
+        $crate::sortedvec! {
+            @extras $extras $name [$($gen)*] [$($args)*] [$($bounds)*] $val, $field
+        }
+
+        impl<$($gen)*> std::ops::Deref for $name<$($args)*> where $($bounds)* {
+            type Target = Vec<$val>;
+
+            fn deref(&self) -> &Self::Target {
+                &self.$field
+            }
+        }
+
+        impl<$($gen)*> std::borrow::Borrow<[$val]> for $name<$($args)*> where $($bounds)* {
+            fn borrow(&self) -> &[$val] {
+                &self.$field
+            }
+        }
+
+        impl<$($gen)*> AsRef<[$val]> for $name<$($args)*> where $($bounds)* {
+            fn as_ref(&self) -> &[$val] {
+                &self.$field
+            }
+        }
+
+        impl<$($gen)*> AsRef<Vec<$val>> for $name<$($args)*> where $($bounds)* {
+            fn as_ref(&self) -> &Vec<$val> {
+                &self.$field
+            }
+        }
+    }
+}
+
+/// A macro that defines a specialized sorted vector data structure on [slice] keys.
+///
+/// It differs from the standard
+/// `sortedvec!` macro in that the generated data structure is sorted on slices. This enables binary
+/// searches to be a bit smarter by skipping the comparison of the start of the slice that was shared
+/// with probes smaller and larger than the current probe.
+///
+/// Note that when your key can be compared as `&[u8]`s, like `&str`, a regular `sortedvec!`
+/// may still be faster as SIMD instructions can be used to compare long byte sequences and there is
+/// less bookkeeping involved.
+///
+/// The generated struct is specific to the given keys and value types. To create the struct,
+/// four bits are required:
+/// - a struct name,
+/// - a value type,
+/// - a slice key type of the form `&[K]`. Since we will sort on these internally, `K` must implement `Ord`,
+/// - a key extraction function of type `FnMut(&T) -> &[K]`.
+///
+/// It matches the following input:
+/// ```text
+/// $(#[$attr:meta])*
+/// $v:vis struct $name:ident
+///     $(< $($lt:lifetime),* $(,)? $($tp:ident $(: $tb:path)?),* $(,)? >)?
+///     $(where $($wty:ty : $wb:path),+ $(,)?)?
+/// {
+///     fn derive_key($i:ident : & $val:ty) -> & [ $key:ty ] {
+///         $keyexpr:expr
+///     } $(,)?
+///     $(options { $($option),* $(,)? })?
+/// }
+/// ```
+///
+/// Like with `sortedvec!`, the struct may have lifetime and type parameters, and secondary
+/// indexes and options can follow the key derivation function. Type parameters named `Iter`,
+/// `KeySlice`, `Other` or `QueryKey` and the lifetime `'sorted` are reserved. Optional keys,
+/// key derivation contexts and `search = interpolation` are not supported.
+///
+/// Both macros share their implementation, except for the search strategy. The exposed methods
+/// and trait implementations are therefore identical to those of a data structure generated by
+/// `sortedvec!`, except that lookup methods take any `AsRef<[K]>` as key. To get an overview of
+/// the exposed methods on the generated structure, see the documentation of the example module.
+///
+/// [slice]: https://doc.rust-lang.org/std/primitive.slice.html
+#[macro_export]
+macro_rules! sortedvec_slicekey {
+(
+    $(#[$attr:meta])*
+    $v:vis struct $name:ident
+        $(< $($lt:lifetime),* $(,)? $($tp:ident $(: $tb:path)?),* $(,)? >)?
+        $(where $($wty:ty : $wb:path),+ $(,)?)?
+    {
+        $($body:tt)*
+    }
+) => {
+        $crate::sortedvec_slicekey! {
+            @key [
+                [$(#[$attr])*] $v $name
+                [$($($lt,)* $($tp $(: $tb)?,)*)?] [$($($lt,)* $($tp,)*)?] [$($($wty: $wb,)+)?]
+            ]
+            $($body)*
+        }
+    };
+(
+    @key [$($front:tt)*]
+    fn derive_key($i:ident : & $val:ty) -> & [ $key:ty ] { $keyexpr:expr } $($rest:tt)*
+) => {
+        $crate::sortedvec! {
+            @indexes [$($front)* [slice $key]] [plain fn derive_key($i: &$val) -> &[$key] [{ $keyexpr }]] []
+            $($rest)*
+        }
+    };
+(
+    @key [$($front:tt)*]
+    fn derive_key($i:ident : & $val:ty) -> & [ $key:ty ]; $($rest:tt)*
+) => {
+        $crate::sortedvec! {
+            @indexes [$($front)* [slice $key]] [plain fn derive_key($i: &$val) -> &[$key] []] []
+            $($rest)*
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn simple() {
+        sortedvec! {
+            #[derive(Eq, PartialEq, Ord, PartialOrd, Debug, Clone, Hash)]
+            pub struct TestVec {
+                fn derive_key(x: &u32) -> u32 { *x }
+            }
+        }
+
+        let sv: TestVec = (0u32..10).collect();
+        assert!(sv.find(&5) == Some(&5));
+        assert_eq!(10, sv.len());
+        let v: Vec<_> = sv.clone().into();
+        assert_eq!((0u32..10).collect::<Vec<_>>(), v);
+    }
+
+    #[test]
+    fn more_complex() {
+        #[derive(Debug, Default)]
+        struct SomeComplexValue {
+            some_map: std::collections::HashMap<String, std::path::PathBuf>,
+            name: String,
+            prio: u64,
+        }
+
+        sortedvec! {
+            /// Vec of `SomeComplexValues` that allows quick
+            /// lookup by (name, prio) keys
+            #[derive(Debug)]
+            struct ComplexMap {
+                fn derive_key(val: &SomeComplexValue) -> (&str, u64) {
+                    (val.name.as_str(), val.prio)
+                }
+            }
+        }
+
+        let mut sv = ComplexMap::default();
+        sv.insert(SomeComplexValue {
+            some_map: Default::default(),
+            name: "test".to_owned(),
+            prio: 0,
+        });
+
+        assert!(sv.len() == 1);
+        assert!(sv.find(&("test", 0)).unwrap().some_map.is_empty());
+        assert!(sv.find(&("hello", 1)).is_none());
+        assert!(sv.remove(&("test", 0)).is_some());
+        assert!(sv.is_empty());
+
+        for val in sv {
+            println!("{:?}", val);
+        }
+    }
+
+    #[test]
+    fn generics() {
+        trait Named {
+            fn name(&self) -> &str;
+        }
+
+        #[derive(Debug, PartialEq)]
+        struct Record {
+            id: u32,
+            name: String,
+        }
+
+        impl Named for Record {
+            fn name(&self) -> &str {
+                &self.name
+            }
+        }
+
+        sortedvec! {
+            #[derive(Debug)]
+            struct ByName<T: Named> {
+                fn derive_key(t: &T) -> &str { t.name() }
+            }
+        }
+
+        sortedvec! {
+            struct RefsByName<'a, T> where T: Named, T: std::fmt::Debug {
+                fn derive_key(t: & &'a T) -> &'a str { t.name() }
+            }
+        }
+
+        let records = vec![
+            Record {
+                id: 2,
+                name: "beta".to_owned(),
+            },
+            Record {
+                id: 1,
+                name: "gamma".to_owned(),
+            },
+            Record {
+                id: 3,
+                name: "alpha".to_owned(),
+            },
+        ];
+        let refs: RefsByName<Record> = records.iter().collect();
+        assert_eq!(1, refs.find(&"gamma").unwrap().id);
+        assert_eq!(vec![3, 2, 1], refs.iter().map(|r| r.id).collect::<Vec<_>>());
+
+        let mut by_name: ByName<Record> = records.into_iter().collect();
+        assert_eq!(3, by_name.find(&"alpha").unwrap().id);
+        assert!(by_name.remove(&"beta").is_some());
+        let names: Vec<_> = by_name.into_iter().map(|r| r.name).collect();
+        assert_eq!(vec!["alpha", "gamma"], names);
+    }
+
+    #[test]
+    fn secondary_indexes() {
+        use std::ops::Bound::{Excluded, Included, Unbounded};
+
+        #[derive(Debug, Clone, PartialEq)]
+        struct Person {
+            id: u32,
+            name: String,
+            age: u8,
+        }
+
+        sortedvec! {
+            #[derive(Debug, Clone)]
+            struct People {
+                fn derive_key(p: &Person) -> u32 { p.id }
+                #[index(find = find_by_name, range = range_by_name)]
+                fn name_key(p: &Person) -> &str { &p.name }
+                #[index(find = find_by_age, range = range_by_age)]
+                fn age_key(p: &Person) -> u8 { p.age }
+            }
+        }
+
+        let person = |id, name: &str, age| Person {
+            id,
+            name: name.to_owned(),
+            age,
+        };
+        let mut people: People = vec![
+            person(3, "carol", 41),
+            person(1, "alice", 30),
+            person(4, "dave", 25),
+        ]
+        .into_iter()
+        .collect();
+        people.insert(person(2, "bob", 35));
+
+        let ids = |it: &mut dyn Iterator<Item = &Person>| it.map(|p| p.id).collect::<Vec<_>>();
+        assert_eq!(Some(2), people.find_by_name(&"bob").map(|p| p.id));
+        assert_eq!(Some(4), people.find_by_age(&25).map(|p| p.id));
+        assert_eq!(
+            vec![1, 2, 3, 4],
+            ids(&mut people.range_by_name(Unbounded, Unbounded))
+        );
+        assert_eq!(
+            vec![2, 3],
+            ids(&mut people.range_by_name(Included(&"b"), Excluded(&"d")))
+        );
+        assert_eq!(
+            vec![1, 2],
+            ids(&mut people.range_by_age(Excluded(&25), Included(&35)))
+        );
+
+        assert_eq!(Some(3), people.remove(&3).map(|p| p.id));
+        assert_eq!(None, people.find_by_name(&"carol"));
+        assert_eq!(
+            vec![4, 1, 2],
+            ids(&mut people.range_by_age(Unbounded, Unbounded))
+        );
+
+        let tail = people.split_off(1);
+        assert_eq!(
+            vec![1],
+            ids(&mut people.range_by_name(Unbounded, Unbounded))
+        );
+        assert_eq!(
+            vec![4, 2],
+            ids(&mut tail.range_by_age(Unbounded, Unbounded))
+        );
+        assert_eq!(Some(1), people.pop().map(|p| p.id));
+        assert_eq!(None, people.find_by_name(&"alice"));
+    }
+
+    #[quickcheck]
+    fn secondary_index_in_sync(xs: Vec<(u8, u8)>, removals: Vec<u8>) -> bool {
+        sortedvec! {
+            struct Pairs {
+                fn derive_key(x: &(u8, u8)) -> u8 { x.0 }
+                #[index(find = find_by_second, range = range_by_second)]
+                fn second(x: &(u8, u8)) -> u8 { x.1 }
+            }
+        }
+
+        let mut pairs = Pairs::default();
+        for x in xs {
+            pairs.insert(x);
+        }
+        for key in removals {
+            pairs.remove(&key);
+        }
+
+        let mut expected: Vec<u8> = pairs.iter().map(|x| x.1).collect();
+        expected.sort();
+        let seconds: Vec<u8> = pairs
+            .range_by_second(std::ops::Bound::Unbounded, std::ops::Bound::Unbounded)
+            .map(|x| x.1)
+            .collect();
+        seconds == expected && pairs.iter().all(|x| pairs.find_by_second(&x.1).is_some())
+    }
+
+    #[test]
+    fn optional_keys() {
+        sortedvec! {
+            #[derive(Debug, Clone)]
+            struct Parsed {
+                #[optional]
+                fn derive_key(x: & &'static str) -> Option<u32> { x.parse().ok() }
+            }
+        }
+
+        let mut parsed: Parsed = vec!["3", "x", "1", "y"].into_iter().collect();
+        parsed.insert("2");
+        parsed.insert("z");
+        parsed.extend(vec!["w", "0"]);
+
+        assert_eq!(&["0", "1", "2", "3"][..], &parsed[..]);
+        assert_eq!(&["x", "y", "z", "w"][..], parsed.unkeyed());
+        assert_eq!(Some(&"2"), parsed.find(&2));
+        assert_eq!(Some("3"), parsed.pop());
+
+        let tail = parsed.split_off(2);
+        assert_eq!(&["2"][..], &tail[..]);
+        assert!(tail.unkeyed().is_empty());
+
+        let all: Vec<_> = parsed.clone().into_iter().collect();
+        assert_eq!(vec!["0", "1", "x", "y", "z", "w"], all);
+        assert_eq!(4, parsed.take_unkeyed().len());
+        assert!(parsed.unkeyed().is_empty());
+
+        assert_eq!(Some(1), parsed.rekey(&0, |x| *x = "v"));
+        assert_eq!(&["1"][..], &parsed[..]);
+        assert_eq!(&["v"][..], parsed.unkeyed());
+        assert_eq!(0, parsed.update_key_at(0, |x| *x = "4"));
+        assert_eq!(0, parsed.update_key_at(0, |x| *x = "u"));
+        assert!(parsed.is_empty());
+        assert_eq!(&["v", "u"][..], parsed.unkeyed());
+    }
+
+    #[test]
+    fn set_operations_keep_unkeyed() {
+        sortedvec! {
+            #[derive(Debug, Clone)]
+            struct Parsed {
+                #[optional]
+                fn derive_key(x: & &'static str) -> Option<u32> { x.parse().ok() }
+            }
+        }
+
+        let left: Parsed = vec!["1", "x", "2"].into_iter().collect();
+        let right: Parsed = vec!["2", "y", "3"].into_iter().collect();
+
+        let union = left.clone().into_union(right.clone());
+        assert_eq!(&["1", "2", "3"][..], &union[..]);
+        assert_eq!(&["x", "y"][..], union.unkeyed());
+
+        let intersection = left.clone().into_intersection(right.clone());
+        assert_eq!(&["2"][..], &intersection[..]);
+        assert!(intersection.unkeyed().is_empty());
+
+        let difference = left.clone().into_difference(right.clone());
+        assert_eq!(&["1"][..], &difference[..]);
+        assert_eq!(&["x"][..], difference.unkeyed());
+
+        let symmetric = left.into_symmetric_difference(right);
+        assert_eq!(&["1", "3"][..], &symmetric[..]);
+        assert_eq!(&["x", "y"][..], symmetric.unkeyed());
+    }
+
+    #[test]
+    fn context() {
+        #[derive(Debug, Clone)]
+        struct Salt(u32);
+
+        sortedvec! {
+            #[derive(Debug, Clone)]
+            struct SaltedVec {
+                fn derive_key(salt: &Salt, x: &u32) -> u32 { x ^ salt.0 }
+            }
+        }
+
+        let mut sv = SaltedVec::with_context(Salt(0b101));
+        sv.extend(vec![0b001, 0b100, 0b111, 0b010]);
+        assert_eq!(&[0b100, 0b111, 0b001, 0b010][..], &sv[..]);
+        assert_eq!(Some(&0b111), sv.find(&0b010));
+
+        sv.insert(0b110);
+        assert_eq!(0b110, sv[2]);
+        assert!(sv.remove(&0b100).is_some());
+
+        let other = sv.split_off(2);
+        assert_eq!(0b101, other.context().0);
+        assert_eq!(Some(&0b010), other.find(&0b111));
+    }
+
+    sortedvec! {
+        #[derive(Debug)]
+        struct XoredVec {
+            fn derive_key(salt: &u32, x: &u32) -> u32 { x ^ salt }
+        }
+    }
+
+    #[test]
+    fn into_set_ops_with_different_contexts() {
+        let lhs = XoredVec::from_vec_with_context(vec![0, 1, 2, 3], 0);
+        let rhs = XoredVec::from_vec_with_context(vec![4, 5, 6, 7], 3);
+        assert_eq!(&[7, 6, 5, 4][..], &rhs[..]);
+
+        let union = lhs.into_union(rhs);
+        assert_eq!(Ok(()), union.validate());
+        assert_eq!(0, *union.context());
+        assert_eq!(&[0, 1, 2, 3, 4, 5, 6, 7][..], &union[..]);
+        assert_eq!(Some(&7), union.find(&7));
+
+        let rhs = XoredVec::from_vec_with_context(vec![1, 2, 5], 6);
+        let intersection = union.into_intersection(rhs);
+        assert_eq!(&[1, 2, 5][..], &intersection[..]);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "not sorted in the order of this one")]
+    fn union_with_different_contexts() {
+        let lhs = XoredVec::from_vec_with_context(vec![0, 1, 2, 3], 0);
+        let rhs = XoredVec::from_vec_with_context(vec![4, 5, 6, 7], 3);
+        let _ = lhs.union(&rhs).count();
+    }
+
+    #[test]
+    fn options() {
+        fn first(x: &(u32, char)) -> u32 {
+            x.0
+        }
+
+        sortedvec! {
+            struct StableVec {
+                fn derive_key(x: &(u32, char)) -> u32 { x.0 }
+                options { stable }
+            }
+        }
+
+        sortedvec! {
+            struct UniqueVec {
+                fn derive_key(x: &(u32, char)) -> u32;
+                options { unique, key_fn = first, }
+            }
+        }
+
+        sortedvec! {
+            struct DescendingVec {
+                fn derive_key(x: &(u32, char)) -> u32 { x.0 }
+                options { order = descending, stable }
+            }
+        }
+
+        let input = vec![(2, 'a'), (1, 'b'), (2, 'c'), (1, 'd'), (2, 'e')];
+
+        let mut stable = StableVec::from(input.clone());
+        assert_eq!(
+            &[(1, 'b'), (1, 'd'), (2, 'a'), (2, 'c'), (2, 'e')][..],
+            &stable[..]
+        );
+        assert_eq!(Ok(2), stable.position(&2));
+        assert_eq!(Some(&(1, 'b')), stable.find(&1));
+        stable.insert((1, 'f'));
+        assert_eq!((1, 'f'), stable[2]);
+
+        let mut unique = UniqueVec::from(input.clone());
+        assert_eq!(&[(1, 'd'), (2, 'e')][..], &unique[..]);
+        unique.insert((1, 'g'));
+        unique.insert((0, 'h'));
+        assert_eq!(&[(0, 'h'), (1, 'g'), (2, 'e')][..], &unique[..]);
+        unique.extend(vec![(0, 'i'), (0, 'j')]);
+        assert_eq!(&[(0, 'j'), (1, 'g'), (2, 'e')][..], &unique[..]);
+
+        let mut descending = DescendingVec::from(input);
+        assert_eq!(
+            &[(2, 'a'), (2, 'c'), (2, 'e'), (1, 'b'), (1, 'd')][..],
+            &descending[..]
+        );
+        assert_eq!(Err(5), descending.position(&0));
+        assert_eq!(Ok(3), descending.position(&1));
+        descending.insert((3, 'k'));
+        assert_eq!((3, 'k'), descending[0]);
+        assert_eq!(Some((1, 'd')), descending.pop());
+    }
+
+    #[test]
+    fn duplicate_policies() {
+        fn count(kept: &mut (u32, u32), dup: (u32, u32)) {
+            kept.1 += dup.1;
+        }
+
+        sortedvec! {
+            struct FirstVec {
+                fn derive_key(x: &(u32, u32)) -> u32 { x.0 }
+                options { unique = first }
+            }
+        }
+
+        sortedvec! {
+            struct Counter {
+                fn derive_key(x: &(u32, u32)) -> u32 { x.0 }
+                options { merge = count }
+            }
+        }
+
+        sortedvec! {
+            struct Plain {
+                fn derive_key(x: &(u32, u32)) -> u32 { x.0 }
+                #[index(find = find_by_count, range = range_by_count)]
+                fn by_count(x: &(u32, u32)) -> u32 { x.1 }
+            }
+        }
+
+        let input = vec![(2, 1), (1, 2), (2, 3), (1, 4), (2, 5)];
+
+        let mut first = FirstVec::from(input.clone());
+        assert_eq!(&[(1, 2), (2, 1)][..], &first[..]);
+        first.insert((1, 6));
+        first.extend(vec![(0, 7), (0, 8)]);
+        assert_eq!(&[(0, 7), (1, 2), (2, 1)][..], &first[..]);
+
+        let mut counter: Counter = input.iter().cloned().collect();
+        assert_eq!(&[(1, 6), (2, 9)][..], &counter[..]);
+        counter.insert((1, 1));
+        counter.extend(vec![(2, 1), (3, 1)]);
+        assert_eq!(&[(1, 7), (2, 10), (3, 1)][..], &counter[..]);
+
+        let mut plain = Plain::from(input.clone());
+        plain.dedup_by_merge(count);
+        assert_eq!(&[(1, 6), (2, 9)][..], &plain[..]);
+        assert_eq!(Some(&(2, 9)), plain.find_by_count(&9));
+
+        let mut last = Plain::from(vec![(1, 1), (1, 2), (2, 3)]);
+        last.dedup_keep_last();
+        assert_eq!(&[(1, 2), (2, 3)][..], &last[..]);
+        assert_eq!(None, last.find_by_count(&1));
+    }
+
+    #[quickcheck]
+    fn stable_mode_matches_stable_sort(xs: Vec<(u8, u8)>, ys: Vec<(u8, u8)>) -> bool {
+        use crate::{KeyExtractor, SortedVec};
+
+        fn key_of(x: &(u8, u8)) -> u8 {
+            x.0 % 8
+        }
+
+        sortedvec! {
+            struct Stable {
+                fn derive_key(x: &(u8, u8)) -> u8;
+                options { stable, key_fn = key_of }
+            }
+        }
+
+        sortedvec_slicekey! {
+            struct StableSlices {
+                fn derive_key(x: &(u8, u8)) -> &[u8] { std::slice::from_ref(&x.0) }
+                options { stable }
+            }
+        }
+
+        struct StableKey;
+
+        impl KeyExtractor for StableKey {
+            type Value = (u8, u8);
+            type Key = u8;
+            const STABLE: bool = true;
+
+            fn key(x: &(u8, u8)) -> u8 {
+                key_of(x)
+            }
+        }
+
+        let mut model = xs.clone();
+        model.extend(ys.iter().cloned());
+        model.sort_by_key(key_of);
+        let mut slices_model = model.clone();
+        slices_model.sort_by_key(|x| x.0);
+
+        let mut stable = Stable::from(xs.clone());
+        let mut generic = SortedVec::<StableKey>::from(xs.clone());
+        let mut slices = StableSlices::from(xs);
+        for &y in &ys {
+            stable.insert(y);
+            generic.insert(y);
+            slices.insert(y);
+        }
+
+        stable[..] == model[..]
+            && generic[..] == model[..]
+            && slices[..] == slices_model[..]
+            && model.iter().all(|x| {
+                let first = model.iter().find(|y| key_of(y) == key_of(x));
+                stable.find(&key_of(x)) == first && generic.find(&key_of(x)) == first
+            })
+    }
+
+    #[test]
+    fn construction_from_sorted() {
+        sortedvec! {
+            #[derive(Debug)]
+            struct Unique {
+                fn derive_key(x: &(u32, char)) -> u32 { x.0 }
+                #[index(find = find_by_char, range = range_by_char)]
+                fn by_char(x: &(u32, char)) -> char { x.1 }
+                options { unique, order = descending }
+            }
+        }
+
+        sortedvec! {
+            #[derive(Debug)]
+            struct Optional {
+                #[optional]
+                fn derive_key(x: & &'static str) -> Option<u32> { x.parse().ok() }
+            }
+        }
+
+        sortedvec! {
+            struct Salted {
+                fn derive_key(salt: &u32, x: &u32) -> u32 { x ^ salt }
+            }
+        }
+
+        let unique = Unique::try_from_sorted(vec![(3, 'a'), (2, 'c'), (1, 'b')]).unwrap();
+        assert_eq!(Some(&(2, 'c')), unique.find_by_char(&'c'));
+        let err = Unique::try_from_sorted(vec![(3, 'a'), (2, 'c'), (2, 'b')]).unwrap_err();
+        assert_eq!(2, err.index());
+        assert_eq!("value at index 2 is out of order", err.to_string());
+        assert_eq!(vec![(3, 'a'), (2, 'c'), (2, 'b')], err.into_vec());
+
+        let optional = Optional::try_from_sorted(vec!["1", "x", "2", "3"]).unwrap();
+        assert_eq!(&["1", "2", "3"], optional.as_slice());
+        assert_eq!(&["x"], optional.unkeyed());
+        assert_eq!(
+            3,
+            Optional::try_from_sorted(vec!["2", "x", "y", "1"])
+                .unwrap_err()
+                .index()
+        );
+
+        let salted = Salted::try_from_sorted_with_context(vec![1, 0, 3, 2], 1).unwrap();
+        assert_eq!(Some(&3), salted.find(&2));
+        assert!(Salted::try_from_sorted_with_context(vec![1, 0, 3, 2], 0).is_err());
+    }
+
+    #[test]
+    #[cfg_attr(
+        any(debug_assertions, feature = "validate"),
+        should_panic(expected = "value at index 2 is out of order")
+    )]
+    fn validate_order() {
+        use crate::InvariantViolation;
+
+        sortedvec! {
+            #[derive(Debug)]
+            struct Numbers {
+                fn derive_key(x: &u32) -> u32 { *x }
+                options { unique }
+            }
+        }
+
+        assert_eq!(Ok(()), Numbers::from(vec![3, 1, 2, 1]).validate());
+        let numbers = unsafe { Numbers::from_sorted_unchecked(vec![1, 2, 2]) };
+        assert_eq!(Err(InvariantViolation::NotSorted(2)), numbers.validate());
+    }
+
+    #[test]
+    fn validate_reordered_keys() {
+        use crate::InvariantViolation;
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        static REVERSED: AtomicBool = AtomicBool::new(false);
+
+        sortedvec! {
+            struct Numbers {
+                fn derive_key(x: &u32) -> u32 {
+                    if REVERSED.load(Ordering::Relaxed) { u32::MAX - x } else { *x }
+                }
+            }
+        }
+
+        let numbers = Numbers::from(vec![3, 1, 2]);
+        assert_eq!(Ok(()), numbers.validate());
+        REVERSED.store(true, Ordering::Relaxed);
+        assert_eq!(Err(InvariantViolation::NotSorted(1)), numbers.validate());
+    }
+
+    #[test]
+    #[cfg_attr(
+        any(debug_assertions, feature = "validate"),
+        should_panic(expected = "key of value at index 0 is not deterministic")
+    )]
+    fn validate_key_determinism() {
+        use crate::InvariantViolation;
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        static CALLS: AtomicU32 = AtomicU32::new(0);
+
+        sortedvec! {
+            struct Drifting {
+                fn derive_key(x: &u32) -> u32 { x + CALLS.fetch_add(1, Ordering::Relaxed) }
+            }
+        }
+
+        let mut drifting = Drifting::default();
+        drifting.insert(1);
+        assert_eq!(
+            Err(InvariantViolation::NonDeterministicKey(0)),
+            drifting.validate()
+        );
+    }
+
+    #[test]
+    fn rekey() {
+        fn add(kept: &mut (u32, u32), dup: (u32, u32)) {
+            kept.1 += dup.1;
+        }
+
+        sortedvec! {
+            struct Queue {
+                fn derive_key(x: &(u32, &'static str)) -> u32 { x.0 }
+                options { order = descending }
+            }
+        }
+
+        sortedvec! {
+            struct Totals {
+                fn derive_key(x: &(u32, u32)) -> u32 { x.0 }
+                options { merge = add }
+            }
+        }
+
+        let mut queue = Queue::from(vec![(5, "a"), (3, "b"), (1, "c"), (3, "d")]);
+        assert_eq!(Some(0), queue.rekey(&1, |x| x.0 = 7));
+        assert_eq!(3, queue.update_key_at(0, |x| x.0 = 2));
+        assert_eq!(2, queue.update_key_at(2, |x| x.0 = 3));
+        assert_eq!(None, queue.rekey(&4, |x| x.0 = 0));
+        assert_eq!(&[(5, "a"), (3, "b"), (3, "d"), (2, "c")], queue.as_slice());
+
+        let mut totals = Totals::from(vec![(1, 10), (2, 20), (3, 30)]);
+        assert_eq!(Some(1), totals.rekey(&3, |x| x.0 = 2));
+        assert_eq!(&[(1, 10), (2, 50)], totals.as_slice());
+    }
+
+    #[quickcheck]
+    fn update_key_at_matches_reinsertion(xs: Vec<(u8, u8)>, idx: usize, key: u8) -> bool {
+        use std::ops::Bound::Unbounded;
+
+        sortedvec! {
+            #[derive(Clone)]
+            struct Tagged {
+                fn derive_key(x: &(u8, u8)) -> u8 { x.0 }
+                #[index(find = find_by_tag, range = range_by_tag)]
+                fn tag(x: &(u8, u8)) -> u8 { x.1 }
+                options { stable }
+            }
+        }
+
+        if xs.is_empty() {
+            return true;
+        }
+        let idx = idx % xs.len();
+        let update = |x: &mut (u8, u8)| *x = (key, x.1.wrapping_add(key));
+
+        let mut rekeyed = Tagged::from(xs);
+        let mut values = rekeyed.to_vec();
+        let mut val = values.remove(idx);
+        update(&mut val);
+        let mut reinserted = Tagged::from(values);
+        reinserted.insert(val);
+
+        let new_idx = rekeyed.update_key_at(idx, update);
+        let tags: Vec<u8> = rekeyed
+            .range_by_tag(Unbounded, Unbounded)
+            .map(|x| x.1)
+            .collect();
+
+        rekeyed[..] == reinserted[..]
+            && rekeyed[new_idx] == val
+            && tags.len() == rekeyed.len()
+            && tags.windows(2).all(|w| w[0] <= w[1])
+            && rekeyed
+                .iter()
+                .all(|x| rekeyed.find_by_tag(&x.1).map(|y| y.1) == Some(x.1))
+    }
+
+    #[test]
+    fn cursors() {
+        use std::ops::Bound::Unbounded;
+
+        sortedvec! {
+            struct Ids {
+                fn derive_key(x: &(u32, char)) -> u32 { x.0 }
+                #[index(find = find_by_tag, range = range_by_tag)]
+                fn tag(x: &(u32, char)) -> char { x.1 }
+                options { unique }
+            }
+        }
+
+        sortedvec_slicekey! {
+            struct Words {
+                fn derive_key(x: &String) -> &[u8] { x.as_bytes() }
+            }
+        }
+
+        let mut ids = Ids::from(vec![(1, 'a'), (4, 'b'), (6, 'c')]);
+        let mut cursor = ids.cursor_at(&4);
+        assert_eq!(1, cursor.index());
+        assert_eq!(Some(&(1, 'a')), cursor.prev());
+        assert_eq!(None, cursor.prev());
+        assert_eq!(Some(&(1, 'a')), cursor.next());
+        assert_eq!(Some(&(1, 'a')), cursor.peek_prev());
+
+        let mut cursor = ids.cursor_mut_at(&5);
+        assert_eq!(Some(&(6, 'c')), cursor.peek());
+        assert_eq!(Err((4, 'x')), cursor.insert_before((4, 'x')));
+        assert_eq!(Err((7, 'x')), cursor.insert_after((7, 'x')));
+        assert_eq!(Ok(()), cursor.insert_before((5, 'd')));
+        assert_eq!(Some((6, 'c')), cursor.remove_current());
+        assert_eq!(None, cursor.remove_current());
+        assert_eq!(Ok(()), cursor.insert_after((9, 'e')));
+        assert_eq!(Some(&(9, 'e')), cursor.next());
+        assert_eq!(None, cursor.next());
+        assert_eq!(&[(1, 'a'), (4, 'b'), (5, 'd'), (9, 'e')], ids.as_slice());
+        assert_eq!(Some(&(5, 'd')), ids.find_by_tag(&'d'));
+        assert_eq!(None, ids.find_by_tag(&'c'));
+        assert_eq!(4, ids.range_by_tag(Unbounded, Unbounded).count());
+
+        let mut words: Words = vec!["ant".to_owned(), "cat".to_owned()].into();
+        let mut cursor = words.cursor_mut_at("bee");
+        assert_eq!(Ok(()), cursor.insert_after("bee".to_owned()));
+        assert_eq!(Ok(()), cursor.insert_before("bat".to_owned()));
+        assert_eq!(Some(&"bee".to_owned()), cursor.next());
+        assert_eq!(&["ant", "bat", "bee", "cat"], &words[..]);
+    }
+
+    #[quickcheck]
+    fn position_from_hint_matches_position(xs: Vec<u8>, keys: Vec<u8>, hint: usize) -> bool {
+        sortedvec! {
+            struct Descending {
+                fn derive_key(x: &u8) -> u8 { *x }
+                options { order = descending }
+            }
+        }
+
+        sortedvec_slicekey! {
+            struct Bytes {
+                fn derive_key(x: &Vec<u8>) -> &[u8] { x }
+            }
+        }
+
+        let numbers = Descending::from(xs.clone());
+        let bytes = Bytes::from(xs.iter().map(|&x| vec![x % 4, x]).collect::<Vec<_>>());
+        // Hinted searches return the first of equal keys, which `position` need not do.
+        let agrees = |hinted: Result<usize, usize>, searched: Result<usize, usize>| match searched {
+            Ok(j) => matches!(hinted, Ok(i) if i <= j),
+            Err(_) => hinted == searched,
+        };
+        keys.iter().enumerate().all(|(i, &key)| {
+            let hint = hint.wrapping_add(i * 7) % (xs.len() + 2);
+            let slice_key = [key % 4, key];
+            let number_idx = numbers.position_from_hint(&key, hint);
+            let bytes_idx = bytes.position_from_hint(slice_key, hint);
+            agrees(number_idx, numbers.position(&key))
+                && number_idx.map_or(true, |i| {
+                    numbers[i] == key && (i == 0 || numbers[i - 1] != key)
+                })
+                && agrees(bytes_idx, bytes.position(slice_key))
+                && bytes_idx.map_or(true, |i| {
+                    bytes[i] == slice_key && (i == 0 || bytes[i - 1] != slice_key)
+                })
+        })
+    }
+
+    #[test]
+    fn position_from_hint_with_duplicates() {
+        sortedvec! {
+            struct Unstable {
+                fn derive_key(x: &(u32, u32)) -> u32 { x.0 }
+            }
+        }
+
+        let pairs = Unstable::from((0..64).map(|i| (i / 16, i)).collect::<Vec<_>>());
+        for key in 0..5 {
+            let first = pairs.iter().position(|x| x.0 >= key).unwrap_or(64);
+            for hint in 0..70 {
+                let hinted = pairs.position_from_hint(&key, hint);
+                assert_eq!(pairs.position(&key).is_ok(), hinted.is_ok());
+                assert_eq!(first, hinted.unwrap_or_else(|i| i));
+            }
+        }
+    }
+
+    #[quickcheck]
+    fn positions_many_matches_position(xs: Vec<u16>, keys: Vec<u16>, sort_keys: bool) -> bool {
+        sortedvec! {
+            struct Stable {
+                fn derive_key(x: &u16) -> u16 { *x / 4 }
+                options { stable }
+            }
+        }
+
+        sortedvec_slicekey! {
+            struct Bytes {
+                fn derive_key(x: &Vec<u8>) -> &[u8] { x }
+            }
+        }
+
+        let mut keys: Vec<u16> = keys.into_iter().map(|key| key / 4).collect();
+        if sort_keys {
+            keys.sort();
+        }
+        let numbers = Stable::from(xs.clone());
+        // Without the `stable` option, `position` may return any of several equal keys.
+        let mut bytes = Bytes::from(
+            xs.iter()
+                .map(|x| x.to_be_bytes().to_vec())
+                .collect::<Vec<_>>(),
+        );
+        bytes.dedup();
+        let byte_keys: Vec<_> = keys.iter().map(|key| (key * 4).to_be_bytes()).collect();
+        let found: Vec<_> = keys.iter().map(|key| numbers.find(key)).collect();
+
+        keys.iter()
+            .map(|key| numbers.position(key))
+            .eq(numbers.positions_many(&keys))
+            && found == numbers.find_many(&keys)
+            && byte_keys
+                .iter()
+                .map(|key| bytes.position(key))
+                .collect::<Vec<_>>()
+                == bytes.positions_many(&byte_keys)
+    }
+
+    #[test]
+    fn positions_many_with_duplicates() {
+        sortedvec! {
+            struct Unstable {
+                fn derive_key(x: &(u32, u32)) -> u32 { x.0 }
+                options { order = descending }
+            }
+        }
+
+        let pairs = Unstable::from((0..64).map(|i| (i / 16, i)).collect::<Vec<_>>());
+        let keys: Vec<u32> = (0..20).map(|i| (i * 7) % 5).collect();
+        let positions = pairs.positions_many(&keys);
+        for (key, idx) in keys.iter().zip(positions) {
+            let first = pairs.iter().position(|x| x.0 <= *key).unwrap_or(64);
+            assert_eq!(pairs.position(key).is_ok(), idx.is_ok());
+            assert_eq!(first, idx.unwrap_or_else(|i| i));
+        }
+        let found = pairs.find_many(&[3, 4]);
+        assert_eq!(vec![Some(&pairs[0]), None], found);
+    }
+
+    #[quickcheck]
+    fn frozen_matches_sorted(xs: Vec<(u8, u8)>, keys: Vec<u8>) -> bool {
+        use std::ops::Bound::Unbounded;
+
+        sortedvec! {
+            #[derive(Clone)]
+            struct Tagged {
+                fn derive_key(x: &(u8, u8)) -> u8 { x.0 }
+                #[index(find = find_by_tag, range = range_by_tag)]
+                fn tag(x: &(u8, u8)) -> u8 { x.1 }
+                options { stable, order = descending }
+            }
+        }
+
+        sortedvec_slicekey! {
+            struct Bytes {
+                fn derive_key(x: &Vec<u8>) -> &[u8] { x }
+            }
+        }
+
+        let tagged = Tagged::from(xs.clone());
+        let frozen = tagged.clone().freeze();
+        let mut sorted_bytes: Vec<_> = xs.iter().map(|x| vec![x.0, x.1]).collect();
+        let frozen_bytes = Bytes::from(sorted_bytes.clone()).freeze();
+        sorted_bytes.sort();
+        let thawed = frozen.clone().thaw();
+
+        frozen.len() == tagged.len()
+            && frozen.iter().eq(tagged.iter())
+            && frozen_bytes.iter().eq(sorted_bytes.iter())
+            && format!("{:?}", frozen_bytes) == format!("{:?}", sorted_bytes)
+            && keys.iter().all(|key| {
+                let first = tagged.position(key).ok().map(|idx| &tagged[idx]);
+                frozen.find(key) == first
+                    && frozen.contains(key) == tagged.contains(key)
+                    && frozen_bytes.contains(&[*key, *key][..]) == xs.contains(&(*key, *key))
+            })
+            && thawed[..] == tagged[..]
+            && thawed
+                .range_by_tag(Unbounded, Unbounded)
+                .map(|x| x.1)
+                .eq(tagged.range_by_tag(Unbounded, Unbounded).map(|x| x.1))
+    }
+
+    #[quickcheck]
+    fn branchless_search_matches_binary(xs: Vec<(u16, u8)>, keys: Vec<u16>) -> bool {
+        sortedvec! {
+            struct Binary {
+                fn derive_key(x: &(u16, u8)) -> u16 { x.0 / 4 }
+                options { order = descending }
+            }
+        }
+
+        sortedvec! {
+            struct Branchless {
+                fn derive_key(x: &(u16, u8)) -> u16 { x.0 / 4 }
+                options { order = descending, search = branchless }
+            }
+        }
+
+        sortedvec_slicekey! {
+            struct Bytes {
+                fn derive_key(x: &Vec<u8>) -> &[u8] { x }
+                options { stable }
+            }
+        }
+
+        sortedvec_slicekey! {
+            struct BranchlessBytes {
+                fn derive_key(x: &Vec<u8>) -> &[u8] { x }
+                options { search = branchless }
+            }
+        }
+
+        let binary = Binary::from(xs.clone());
+        let branchless = Branchless::from(xs.clone());
+        let byte_values: Vec<Vec<u8>> = xs.iter().map(|x| vec![x.1 % 4, x.1]).collect();
+        let bytes = Bytes::from(byte_values.clone());
+        let branchless_bytes = BranchlessBytes::from(byte_values);
+
+        keys.iter().all(|&key| {
+            let byte_key = [key as u8 % 4, key as u8];
+            let found = branchless.position(&(key / 4));
+            // Unlike `branchless`, the binary search may find any of several equal keys.
+            found.map(|idx| branchless[idx].0 / 4) == binary.position(&(key / 4)).map(|_| key / 4)
+                && found.map_or(true, |idx| idx == 0 || branchless[idx - 1].0 / 4 != key / 4)
+                && branchless_bytes.position(byte_key) == bytes.position(byte_key)
+        })
+    }
+
+    #[quickcheck]
+    fn interpolation_search_matches_binary(
+        xs: Vec<(i16, u8)>,
+        keys: Vec<i16>,
+        dense: bool,
+    ) -> bool {
+        sortedvec! {
+            struct Binary {
+                fn derive_key(x: &(i16, u8)) -> i16 { x.0 }
+                options { stable }
+            }
+        }
+
+        sortedvec! {
+            struct Interpolated {
+                fn derive_key(x: &(i16, u8)) -> i16 { x.0 }
+                options { search = interpolation }
+            }
+        }
+
+        sortedvec! {
+            struct Descending {
+                fn derive_key(x: &(i16, u8)) -> i16 { x.0 }
+                options { order = descending, search = interpolation }
+            }
+        }
+
+        // Besides arbitrary keys, check runs of consecutive keys, which interpolation finds
+        // in a single step.
+        let xs: Vec<_> = if dense {
+            (0..xs.len() as i16).map(|key| (key * 3, 0)).collect()
+        } else {
+            xs
+        };
+        let binary = Binary::from(xs.clone());
+        let interpolated = Interpolated::from(xs.clone());
+        let descending = Descending::from(xs);
+
+        keys.iter().all(|key| {
+            let reversed = binary.len() - binary.partition_point(|x| x.0 <= *key);
+            interpolated.position(key) == binary.position(key)
+                && match descending.position(key) {
+                    Ok(idx) => {
+                        descending[idx].0 == *key && (idx == 0 || descending[idx - 1].0 != *key)
                     }
+                    Err(idx) => idx == reversed && !binary.contains(key),
                 }
+        })
+    }
 
-                (prefix_len, slice.len().cmp(&other.len()))
-            }
+    #[test]
+    fn interpolation_search_worst_case() {
+        use crate::merge::interpolation_search;
+        use std::cell::Cell;
 
-            /// Finds and returns reference to element with given key, if it exists.
-            /// Implementation largely taken from `::std::vec::Vec::binary_search_by`.
-            pub fn find<E: AsRef<[$key]>>(&self, init_key: E) -> Option<&$val> {
-                // The unsafe block is OK because `position` is guaranteed to
-                // return a valid index.
-                self.position(init_key).ok().map(|ix| unsafe { self.inner.get_unchecked(ix) })
-            }
+        // A single outlier makes every estimate land at the start of the window, so that plain
+        // interpolation search would take linear time.
+        let mut keys: Vec<u64> = (0..100_000).collect();
+        keys.push(u64::MAX);
+        let probes = Cell::new(0);
+        let search = |target: u64| {
+            probes.set(0);
+            let is_less = |key: &u64| {
+                probes.set(probes.get() + 1);
+                *key < target
+            };
+            interpolation_search(&keys, target, |key| *key, is_less)
+        };
 
-            /// Checks whether there is a value with that key in the collection. This is
-            /// done in `O(log(n))` time.
-            pub fn contains<E: AsRef<[$key]>>(&self, key: E) -> bool {
-                self.position(key).is_ok()
-            }
+        for &target in &[0, 1, 50_000, 99_998, 99_999, 100_000, u64::MAX] {
+            assert_eq!(keys.partition_point(|key| *key < target), search(target));
+            assert!(
+                probes.get() <= 3 * 17 + 3,
+                "{} probes for {}",
+                probes.get(),
+                target
+            );
+        }
+    }
 
-            /// Removes and returns a single value from the collection with the given key,
-            /// if it exists. This operation has linear worst-case time complexity.
-            pub fn remove<E: AsRef<[$key]>>(&mut self, key: E) -> Option<$val> {
-                self.position(key)
-                    .ok()
-                    .map(|idx| self.inner.remove(idx))
-            }
+    #[test]
+    fn sorted_collection() {
+        use crate::SortedCollection;
 
-            /// Inserts a new value into the collection, maintaining the internal
-            /// order invariant. This is an `O(n)` operation.
-            pub fn insert(&mut self, val: $val) {
-                let ref key = Self::derive_key(&val);
-                let idx = match self.position(key) {
-                    Ok(i) | Err(i) => i,
-                };
-                self.inner.insert(idx, val);
+        sortedvec! {
+            struct Strings {
+                fn derive_key(x: &String) -> &str { x }
             }
+        }
 
-            /// Splits the collection into two at the given index.
-            ///
-            /// Returns a newly allocated `Self`. `self` contains elements `[0, at)`,
-            /// and the returned `Self` contains elements `[at, len)`.
-            ///
-            /// Note that the capacity of `self` does not change.
-            ///
-            /// # Panics
-            ///
-            /// Panics if `at > len`.
-            pub fn split_off(&mut self, at: usize) -> Self {
-                let other_inner = self.inner.split_off(at);
-                Self {
-                    inner: other_inner,
-                }
+        fn remove_all<K: ?Sized, C: SortedCollection<K>>(collection: &mut C, key: &K) -> usize {
+            let len = collection.len();
+            while collection.remove(key).is_some() {}
+            len - collection.len()
+        }
+
+        let mut strings: Strings = vec!["a".to_owned(), "b".to_owned(), "b".to_owned()].into();
+        assert_eq!(2, remove_all(&mut strings, &"b"));
+        assert_eq!(Ok(0), SortedCollection::position(&strings, &"a"));
+        assert!(!SortedCollection::is_empty(&strings));
+    }
+
+    #[test]
+    fn standard_impls() {
+        sortedvec! {
+            #[derive(Clone, PartialEq, Eq, Hash)]
+            struct Fixture {
+                fn derive_key(x: &(&'static str, u32)) -> &'static str { x.0 }
+                options { partial_eq, debug_map }
             }
+        }
 
-            /// Removes all elements but one that resolve to the same key.
-            pub fn dedup(&mut self) {
-                self.inner.dedup_by(|a, b| Self::derive_key(a) == Self::derive_key(b));
+        let fixture = Fixture::from(vec![("b", 2), ("a", 1)]);
+        assert_eq!(fixture, vec![("a", 1), ("b", 2)]);
+        assert_eq!(vec![("a", 1), ("b", 2)], fixture);
+        assert!(fixture == [("a", 1), ("b", 2)][..]);
+        assert_eq!(
+            r#"{"a": ("a", 1), "b": ("b", 2)}"#,
+            format!("{:?}", fixture)
+        );
+
+        assert_eq!(&("b", 2), &fixture[&"b"]);
+        assert_eq!(&("a", 1), &fixture[0]);
+        assert_eq!(&[("b", 2)], &fixture[1..]);
+        assert!(fixture == fixture.clone());
+    }
+
+    #[test]
+    #[should_panic(expected = "no value found for key")]
+    fn index_missing_key() {
+        let sv = crate::example::ExampleSortedVec::from(vec![("a".to_owned(), 1)]);
+        let _ = &sv[&2];
+    }
+
+    #[test]
+    fn set_operations() {
+        sortedvec! {
+            #[derive(Debug, Clone)]
+            struct Tagged {
+                fn derive_key(x: &(u32, char)) -> u32 { x.0 }
             }
+        }
 
-            /// Removes and returns the greatest element with the respect to
-            /// the generated keys. An `O(1)` operation.
-            pub fn pop(&mut self) -> Option<$val> {
-                self.inner.pop()
+        let a = Tagged::from(vec![(1, 'a'), (2, 'a'), (2, 'a'), (4, 'a'), (7, 'a')]);
+        let b = Tagged::from(vec![(2, 'b'), (3, 'b'), (4, 'b'), (8, 'b')]);
+        let collect =
+            |iter: &mut dyn Iterator<Item = &(u32, char)>| iter.cloned().collect::<Vec<_>>();
+
+        let union = vec![
+            (1, 'a'),
+            (2, 'a'),
+            (2, 'a'),
+            (3, 'b'),
+            (4, 'a'),
+            (7, 'a'),
+            (8, 'b'),
+        ];
+        assert_eq!(union, collect(&mut a.union(&b)));
+        assert_eq!(union, Vec::from(a.clone().into_union(b.clone())));
+
+        let intersection = vec![(2, 'a'), (4, 'a')];
+        assert_eq!(intersection, collect(&mut a.intersection(&b)));
+        assert_eq!(
+            intersection,
+            Vec::from(a.clone().into_intersection(b.clone()))
+        );
+
+        let difference = vec![(1, 'a'), (2, 'a'), (7, 'a')];
+        assert_eq!(difference, collect(&mut a.difference(&b)));
+        assert_eq!(difference, Vec::from(a.clone().into_difference(b.clone())));
+
+        let symmetric = vec![(1, 'a'), (2, 'a'), (3, 'b'), (7, 'a'), (8, 'b')];
+        assert_eq!(symmetric, collect(&mut a.symmetric_difference(&b)));
+        assert_eq!(
+            symmetric,
+            Vec::from(a.clone().into_symmetric_difference(b.clone()))
+        );
+
+        assert!(Tagged::from(vec![(2, 'c'), (4, 'c')]).is_subset(&a));
+        assert!(!a.is_subset(&b));
+        assert!(Tagged::from(vec![(5, 'c')]).is_disjoint(&a));
+        assert!(!a.is_disjoint(&b));
+    }
+
+    #[quickcheck]
+    fn set_operations_count_keys(xs: Vec<u8>, ys: Vec<u8>) -> bool {
+        sortedvec! {
+            #[derive(Clone)]
+            struct Bytes {
+                fn derive_key(x: &u8) -> u8 { *x }
             }
+        }
 
-            // private method
-            fn sort(&mut self) {
-                self.inner.sort_unstable_by(|a, b| {
-                    let lhs = Self::derive_key(a);
-                    let rhs = Self::derive_key(b);
-                    lhs.cmp(&rhs)
-                })
+        let (a, b) = (Bytes::from(xs), Bytes::from(ys));
+        let count = |v: &[u8], k: u8| v.iter().filter(|&&x| x == k).count();
+        let sorted = |v: &[u8]| v.windows(2).all(|w| w[0] <= w[1]);
+        let union: Vec<u8> = a.union(&b).cloned().collect();
+        let intersection: Vec<u8> = a.intersection(&b).cloned().collect();
+        let difference: Vec<u8> = a.difference(&b).cloned().collect();
+        let symmetric: Vec<u8> = a.symmetric_difference(&b).cloned().collect();
+
+        (0..=255).all(|k| {
+            let (m, n) = (count(&a, k), count(&b, k));
+            count(&union, k) == std::cmp::max(m, n)
+                && count(&intersection, k) == std::cmp::min(m, n)
+                && count(&difference, k) == m.saturating_sub(n)
+                && count(&symmetric, k) == std::cmp::max(m, n) - std::cmp::min(m, n)
+        }) && [&union, &intersection, &difference, &symmetric]
+            .iter()
+            .all(|v| sorted(v))
+            && intersection == Vec::from(a.clone().into_intersection(b.clone()))
+            && symmetric == Vec::from(a.into_symmetric_difference(b))
+    }
+
+    #[test]
+    fn joins() {
+        type User = (String, u32);
+        type Order = (&'static str, u32);
+
+        sortedvec! {
+            struct Users {
+                fn derive_key(user: &User) -> &str { &user.0 }
             }
         }
 
-        impl Into<Vec<$val>> for $name {
-            fn into(self) -> Vec<$val> {
-                self.inner
+        sortedvec! {
+            struct Orders {
+                fn derive_key(order: &Order) -> &'static str { order.0 }
+                options { order = descending }
             }
         }
 
-        impl std::default::Default for $name {
-            fn default() -> Self {
-                Self { inner: std::default::Default::default() }
+        let users = Users::from(vec![
+            ("bob".to_owned(), 1),
+            ("alice".to_owned(), 2),
+            ("bob".to_owned(), 3),
+            ("carol".to_owned(), 4),
+        ]);
+        let orders = Orders::from(vec![("dave", 1), ("bob", 2), ("alice", 3), ("bob", 4)]);
+        let mut ascending = orders.to_vec();
+        ascending.reverse();
+        fn order_key(order: &Order) -> &str {
+            order.0
+        }
+        fn ids(groups: (&[User], &[Order])) -> (Vec<u32>, Vec<u32>) {
+            let (users, orders) = groups;
+            let user_ids = users.iter().map(|user| user.1).collect();
+            let order_ids = orders.iter().map(|order| order.1).collect();
+            (user_ids, order_ids)
+        }
+
+        let inner: Vec<_> = users.inner_join(&ascending, order_key).map(ids).collect();
+        assert_eq!(vec![(vec![2], vec![3]), (vec![1, 3], vec![4, 2])], inner);
+
+        let left: Vec<_> = users.left_join(&ascending, order_key).map(ids).collect();
+        assert_eq!(
+            vec![
+                (vec![2], vec![3]),
+                (vec![1, 3], vec![4, 2]),
+                (vec![4], vec![])
+            ],
+            left
+        );
+
+        let full: Vec<_> = users.full_join(&ascending, order_key).map(ids).collect();
+        assert_eq!(
+            vec![
+                (vec![2], vec![3]),
+                (vec![1, 3], vec![4, 2]),
+                (vec![4], vec![]),
+                (vec![], vec![1])
+            ],
+            full
+        );
+
+        assert_eq!(2, users.semi_join(&ascending, order_key).count());
+        let anti: Vec<_> = users.anti_join(&ascending, order_key).collect();
+        assert_eq!(vec![&[("carol".to_owned(), 4)][..]], anti);
+        assert_eq!(0, users.inner_join(&[], order_key).count());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "not sorted in the order of the collection")]
+    fn join_in_other_direction() {
+        sortedvec! {
+            struct Ascending {
+                fn derive_key(x: &u32) -> u32 { *x }
             }
         }
 
-        impl Extend<$val> for $name {
-            fn extend<I>(&mut self, iter: I)
-            where
-                I: IntoIterator<Item = $val>,
-            {
-                self.inner.extend(iter);
-                self.sort();
+        sortedvec! {
+            struct Descending {
+                fn derive_key(x: &u32) -> u32 { *x }
+                options { order = descending }
             }
         }
 
-        impl std::iter::FromIterator<$val> for $name {
-            fn from_iter<I: std::iter::IntoIterator<Item=$val>>(iter: I) -> Self {
-                let inner = Vec::from_iter(iter);
-                From::from(inner)
+        let ascending = Ascending::from(vec![1, 2, 3]);
+        let descending = Descending::from(vec![1, 2, 3]);
+        let _ = ascending.inner_join(&descending, |x| *x).count();
+    }
+
+    #[quickcheck]
+    fn full_join_groups_keys(xs: Vec<(u8, u8)>, ys: Vec<(u8, i16)>) -> bool {
+        sortedvec! {
+            struct Left {
+                fn derive_key(x: &(u8, u8)) -> u8 { x.0 % 16 }
             }
         }
 
-        impl From<Vec<$val>> for $name {
-            fn from(vec: Vec<$val>) -> Self {
-                let mut res = Self { inner: vec };
-                res.sort();
-                res
+        sortedvec! {
+            struct Right {
+                fn derive_key(y: &(u8, i16)) -> u8 { y.0 % 16 }
             }
         }
 
-        impl std::ops::Deref for $name {
-            type Target = Vec<$val>;
+        let (left, right) = (Left::from(xs.clone()), Right::from(ys.clone()));
+        let groups: Vec<_> = left.full_join(&right, |y| y.0 % 16).collect();
+        let keys: Vec<u8> = groups
+            .iter()
+            .map(|(l, r)| l.first().map(|x| x.0).unwrap_or_else(|| r[0].0) % 16)
+            .collect();
 
-            fn deref(&self) -> &Self::Target {
-                &self.inner
+        keys.windows(2).all(|w| w[0] < w[1])
+            && (0..16).all(|k| {
+                let group = keys.iter().position(|&key| key == k).map(|i| groups[i]);
+                let in_left = xs.iter().filter(|x| x.0 % 16 == k).count();
+                let in_right = ys.iter().filter(|y| y.0 % 16 == k).count();
+                match group {
+                    Some((l, r)) => {
+                        l.len() == in_left
+                            && r.len() == in_right
+                            && l.iter().all(|x| x.0 % 16 == k)
+                            && r.iter().all(|y| y.0 % 16 == k)
+                    }
+                    None => in_left == 0 && in_right == 0,
+                }
+            })
+    }
+
+    #[test]
+    fn diff_and_patch() {
+        use crate::diff::Change;
+
+        sortedvec! {
+            #[derive(Debug, Clone)]
+            struct Table {
+                fn derive_key(x: &(u32, char)) -> u32 { x.0 }
+                options { partial_eq }
             }
         }
 
-        impl std::borrow::Borrow<[$val]> for $name {
-            fn borrow(&self) -> &[$val] {
-                &self.inner
+        let old = Table::from(vec![(1, 'a'), (2, 'a'), (2, 'b'), (3, 'a'), (5, 'a')]);
+        let new = Table::from(vec![(1, 'b'), (2, 'a'), (2, 'c'), (4, 'a'), (5, 'a')]);
+        let changes: Vec<_> = old.diff(&new).collect();
+        assert_eq!(
+            vec![
+                Change::Changed(&(1, 'a'), &(1, 'b')),
+                Change::Removed(&(2, 'a')),
+                Change::Removed(&(2, 'b')),
+                Change::Added(&(2, 'a')),
+                Change::Added(&(2, 'c')),
+                Change::Removed(&(3, 'a')),
+                Change::Added(&(4, 'a')),
+            ],
+            changes
+        );
+        assert_eq!(
+            0,
+            old.diff_by(&new, |_, _| true)
+                .filter(|change| matches!(change, Change::Changed(..)))
+                .count()
+        );
+
+        let mut patched = old.clone();
+        patched.apply_patch(changes.into_iter().map(Change::cloned));
+        assert_eq!(new, patched[..]);
+
+        sortedvec! {
+            struct Unique {
+                fn derive_key(x: &(u32, char)) -> u32 { x.0 }
+                options { unique, order = descending }
             }
         }
 
-        impl AsRef<[$val]> for $name {
-            fn as_ref(&self) -> &[$val] {
-                &self.inner
+        let mut unique = Unique::from(vec![(1, 'a'), (2, 'a')]);
+        let patch = vec![
+            Change::Added((3, 'a')),
+            Change::Added((2, 'b')),
+            Change::Removed((1, 'z')),
+            Change::Changed((0, 'a'), (0, 'b')),
+        ];
+        unique.apply_patch_by(patch, PartialEq::eq);
+        assert_eq!(&[(3, 'a'), (2, 'b'), (1, 'a'), (0, 'b')], &unique[..]);
+    }
+
+    #[test]
+    fn patch_matches_values() {
+        use crate::diff::Change;
+
+        sortedvec! {
+            #[derive(Debug, Clone)]
+            struct Table {
+                fn derive_key(x: &(u32, char)) -> u32 { x.0 }
+                options { stable, partial_eq }
             }
         }
 
-        impl AsRef<Vec<$val>> for $name {
-            fn as_ref(&self) -> &Vec<$val> {
-                &self.inner
+        let mut table = Table::from(vec![(1, 'a'), (2, 'a'), (2, 'b'), (2, 'c')]);
+        table.apply_patch(vec![
+            Change::Removed((2, 'b')),
+            Change::Changed((2, 'c'), (2, 'd')),
+            Change::Removed((2, 'z')),
+            Change::Added((2, 'e')),
+        ]);
+        assert_eq!(&[(1, 'a'), (2, 'a'), (2, 'd'), (2, 'e')][..], &table[..]);
+
+        sortedvec! {
+            #[derive(Debug, Clone)]
+            struct Optional {
+                #[optional]
+                fn derive_key(x: &(Option<u32>, char)) -> Option<u32> { x.0 }
+                options { partial_eq }
             }
         }
+
+        let mut optional = Optional::from(vec![(Some(1), 'a'), (None, 'a'), (None, 'b')]);
+        optional.apply_patch(vec![
+            Change::Added((None, 'c')),
+            Change::Removed((None, 'a')),
+            Change::Changed((None, 'b'), (None, 'd')),
+            Change::Added((Some(2), 'a')),
+        ]);
+        assert_eq!(&[(Some(1), 'a'), (Some(2), 'a')][..], &optional[..]);
+        assert_eq!(&[(None, 'd'), (None, 'c')][..], optional.unkeyed());
     }
-}
 
-#[cfg(test)]
-#[allow(unused_variables)]
-mod tests {
     #[test]
-    fn simple() {
+    #[should_panic(expected = "the changes of the patch are not ordered by key")]
+    fn patch_out_of_order() {
+        use crate::diff::Change;
+
         sortedvec! {
-            #[derive(Eq, PartialEq, Ord, PartialOrd, Debug, Clone, Hash)]
-            pub struct TestVec {
-                fn derive_key(x: &u32) -> u32 { *x }
+            struct Table {
+                fn derive_key(x: &(u32, char)) -> u32 { x.0 }
+                options { order = descending, partial_eq }
             }
         }
 
-        let sv: TestVec = (0u32..10).collect();
-        assert!(sv.find(&5) == Some(&5));
-        assert_eq!(10, sv.len());
-        let v: Vec<_> = sv.clone().into();
+        let mut table = Table::from(vec![(1, 'a'), (3, 'a')]);
+        table.apply_patch(vec![Change::Removed((1, 'a')), Change::Added((2, 'a'))]);
     }
 
     #[test]
-    fn more_complex() {
-        #[derive(Debug, Default)]
-        struct SomeComplexValue {
-            some_map: std::collections::HashMap<String, std::path::PathBuf>,
-            name: String,
-            prio: u64,
+    #[should_panic(expected = "value at index 2 is out of order")]
+    fn merge_out_of_order() {
+        sortedvec! {
+            struct Table {
+                fn derive_key(x: &(u32, char)) -> u32 { x.0 }
+            }
         }
 
+        let mut table = Table::from(vec![(1, 'a'), (2, 'a'), (2, 'b'), (3, 'a')]);
+        table.dedup_by_merge(|kept, _| kept.0 = 4);
+    }
+
+    #[quickcheck]
+    fn patch_reproduces_diffed(xs: Vec<(u8, bool)>, ys: Vec<(u8, bool)>) -> bool {
+        use crate::diff::Change;
+
         sortedvec! {
-            /// Vec of `SomeComplexValues` that allows quick
-            /// lookup by (name, prio) keys
-            #[derive(Debug)]
-            struct ComplexMap {
-                fn derive_key(val: &SomeComplexValue) -> (&str, u64) {
-                    (val.name.as_str(), val.prio)
-                }
+            #[derive(Clone)]
+            struct Table {
+                fn derive_key(x: &(u8, bool)) -> u8 { x.0 % 8 }
+                options { partial_eq }
             }
         }
 
-        let mut sv = ComplexMap::default();
-        sv.insert(SomeComplexValue {
-            some_map: Default::default(),
-            name: "test".to_owned(),
-            prio: 0,
-        });
+        let (old, new) = (Table::from(xs), Table::from(ys));
+        let mut patched = old.clone();
+        patched.apply_patch(old.diff(&new).map(Change::cloned));
 
-        assert!(sv.len() == 1);
-        assert!(sv.find(&("hello", 1)).is_none());
-        assert!(sv.remove(&("test", 0)).is_some());
-        assert!(sv.is_empty());
+        patched == new[..] && new.diff(&new).next().is_none()
+    }
 
-        for val in sv {
-            println!("{:?}", val);
+    #[test]
+    fn float_keys() {
+        use crate::float::{NanFirst, NanLast};
+
+        sortedvec! {
+            struct NanFirstVec {
+                fn derive_key(x: &f64) -> NanFirst<f64> { NanFirst(*x) }
+            }
+        }
+
+        sortedvec! {
+            struct NanLastVec {
+                fn derive_key(x: &f64) -> NanLast<f64> { NanLast(*x) }
+            }
         }
+
+        let values = vec![2.0, f64::NAN, -1.0, 0.5, f64::NAN, 1.0];
+        let first = NanFirstVec::from(values.clone());
+        let last = NanLastVec::from(values);
+
+        assert!(first[0].is_nan() && first[1].is_nan());
+        assert_eq!(&first[2..], &[-1.0, 0.5, 1.0, 2.0]);
+        assert!(last[4].is_nan() && last[5].is_nan());
+        assert_eq!(&last[..4], &[-1.0, 0.5, 1.0, 2.0]);
+        assert!(last.contains(&NanLast(f64::NAN)));
+
+        assert_eq!(Some(&1.0), last.find_within(&NanLast(0.8), 0.25));
+        assert_eq!(Some(&0.5), last.find_within(&NanLast(0.7), 0.25));
+        assert_eq!(Some(&2.0), last.find_within(&NanLast(2.1), 0.25));
+        assert_eq!(Some(&-1.0), first.find_within(&NanFirst(-1.1), 0.25));
+        assert_eq!(None, last.find_within(&NanLast(1.5), 0.25));
+        assert_eq!(None, last.find_within(&NanLast(f64::NAN), 0.25));
     }
 }
 
 #[cfg(test)]
 mod slices_tests {
-    use super::*;
-
     sortedvec_slicekey! {
         #[derive(Debug, Clone)]
         pub struct SortedVecOfListLikes {
@@ -597,6 +4456,27 @@ mod slices_tests {
         sorted.find(&s).is_some() == xs.into_iter().any(|x| x == s)
     }
 
+    #[quickcheck]
+    fn insert_keeps_order(xs: Vec<String>, s: String) -> bool {
+        let mut sorted = SortedVecOfListLikes::from(xs);
+        sorted.insert(s);
+
+        sorted.windows(2).all(|w| w[0] <= w[1])
+    }
+
+    #[test]
+    fn generic_values() {
+        sortedvec_slicekey! {
+            struct SortedByBytes<T> where T: AsRef<[u8]> {
+                fn derive_key(t: &T) -> &[u8] { t.as_ref() }
+            }
+        }
+
+        let sorted: SortedByBytes<&str> = vec!["b", "c", "a"].into_iter().collect();
+        assert_eq!(Ok(1), sorted.position("b"));
+        assert_eq!(Some(&"c"), sorted.find("c"));
+    }
+
     #[test]
     fn bad_case() {
         let case = &[
@@ -604,10 +4484,275 @@ mod slices_tests {
             "", "\u{80}", "", "\u{80}", "", "¤\u{0}", "¥", "", "", "¥", "", "\u{80}", "", "", "¥",
             "\u{80}", "",
         ];
-        let sorted: SortedVecOfListLikes = case.into_iter().map(|&x| x.to_owned()).collect();
+        let sorted: SortedVecOfListLikes = case.iter().map(|&x| x.to_owned()).collect();
 
         for s in case {
             assert_eq!(s, sorted.find(s.as_bytes()).unwrap());
         }
     }
 }
+
+#[cfg(test)]
+mod conformance_tests {
+    // Runs the same tests against both macros, so that they stay interchangeable.
+    macro_rules! conformance {
+        (
+            $suite:ident, $mac:ident,
+            key = [$($key:tt)*], derive = $derive:path, query = $query:expr,
+            collection_key = $ck:ty
+        ) => {
+            mod $suite {
+                use crate::SortedCollection;
+
+                crate::$mac! {
+                    #[derive(Debug, Clone)]
+                    struct Words {
+                        fn derive_key(x: &String) -> $($key)* { $derive(x) }
+                    }
+                }
+
+                crate::$mac! {
+                    struct Options {
+                        fn derive_key(x: &String) -> $($key)* { $derive(x) }
+                        options { unique, order = descending }
+                    }
+                }
+
+                crate::$mac! {
+                    struct Indexed {
+                        fn derive_key(x: &String) -> $($key)* { $derive(x) }
+                        #[index(find = find_by_len, range = range_by_len)]
+                        fn len_key(x: &String) -> usize { x.len() }
+                    }
+                }
+
+                fn strings(xs: &[&str]) -> Vec<String> {
+                    xs.iter().map(|&x| x.to_owned()).collect()
+                }
+
+                fn words() -> Words {
+                    Words::from(strings(&["delta", "alpha", "charlie", "bravo"]))
+                }
+
+                #[test]
+                fn trait_impls() {
+                    fn implements<T>()
+                    where
+                        T: Default
+                            + Extend<String>
+                            + std::iter::FromIterator<String>
+                            + From<Vec<String>>
+                            + IntoIterator<Item = String>
+                            + std::ops::Deref<Target = Vec<String>>
+                            + std::borrow::Borrow<[String]>
+                            + for<'a> std::ops::Index<&'a $ck, Output = String>
+                            + std::ops::Index<usize, Output = String>
+                            + std::ops::Index<std::ops::RangeFull, Output = [String]>
+                            + AsRef<[String]>
+                            + AsRef<Vec<String>>
+                            + SortedCollection<$ck, Value = String>,
+                        Vec<String>: From<T>,
+                    {
+                    }
+
+                    implements::<Words>();
+                    implements::<Options>();
+                    implements::<Indexed>();
+                }
+
+                #[test]
+                fn construction() {
+                    let expected = strings(&["alpha", "bravo", "charlie", "delta"]);
+                    assert_eq!(expected, Vec::from(words()));
+                    assert_eq!(expected, words().into_iter().collect::<Vec<_>>());
+
+                    let collected: Words = expected.iter().rev().cloned().collect();
+                    assert_eq!(&expected, &*collected);
+
+                    let mut extended = Words::default();
+                    extended.extend(expected.iter().rev().cloned());
+                    assert_eq!(&expected, &*extended);
+
+                    let sorted = Words::try_from_sorted(expected.clone()).unwrap();
+                    assert_eq!(&expected[..], sorted.as_slice());
+                    let unsorted = strings(&["alpha", "bravo", "delta", "charlie"]);
+                    assert_eq!(3, Words::try_from_sorted(unsorted).unwrap_err().index());
+                    let unchecked = unsafe { Words::from_sorted_unchecked(expected.clone()) };
+                    assert_eq!(expected, unchecked.into_inner());
+                }
+
+                #[test]
+                fn lookups() {
+                    let mut words = words();
+                    assert_eq!(Ok(1), words.position(&($query)("bravo")));
+                    assert_eq!(Err(2), words.position(&($query)("bz")));
+                    assert_eq!(Some("charlie"), words.find(&($query)("charlie")).map(|x| &x[..]));
+                    assert!(words.contains(&($query)("delta")));
+                    assert!(!words.contains(&($query)("echo")));
+
+                    words.insert("echo".to_owned());
+                    assert_eq!(Ok(4), words.position(&($query)("echo")));
+                    assert_eq!(Some("alpha".to_owned()), words.remove(&($query)("alpha")));
+                    assert_eq!(None, words.remove(&($query)("alpha")));
+                    assert_eq!(4, SortedCollection::len(&words));
+                }
+
+                #[test]
+                fn split_dedup_pop() {
+                    let mut words = words();
+                    words.extend(strings(&["alpha", "alpha"]));
+                    words.dedup();
+                    assert_eq!(4, words.len());
+
+                    let tail = words.split_off(2);
+                    assert_eq!(strings(&["charlie", "delta"]), Vec::from(tail));
+                    assert_eq!(Some("bravo".to_owned()), words.pop());
+                    assert_eq!(strings(&["alpha"]), Vec::from(words));
+                }
+
+                #[test]
+                fn options() {
+                    let mut options = Options::from(strings(&["b", "a", "c", "a"]));
+                    assert_eq!(&strings(&["c", "b", "a"]), &*options);
+                    options.insert("b".to_owned());
+                    assert_eq!(Ok(1), options.position(&($query)("b")));
+                    assert_eq!(3, options.len());
+                }
+
+                #[test]
+                fn secondary_index() {
+                    let mut indexed = Indexed::from(strings(&["ccc", "a", "bb"]));
+                    assert_eq!(Some("bb"), indexed.find_by_len(&2).map(|x| &x[..]));
+                    indexed.remove(&($query)("bb"));
+                    assert_eq!(None, indexed.find_by_len(&2));
+                    let lens: Vec<_> = indexed
+                        .range_by_len(std::ops::Bound::Unbounded, std::ops::Bound::Unbounded)
+                        .map(|x| x.len())
+                        .collect();
+                    assert_eq!(vec![1, 3], lens);
+                }
+
+                #[test]
+                fn set_operations() {
+                    let other = Words::from(strings(&["echo", "alpha", "charlie"]));
+                    let collect = |vals: Vec<&String>| vals.into_iter().cloned().collect();
+
+                    let union = strings(&["alpha", "bravo", "charlie", "delta", "echo"]);
+                    let iter: Vec<String> = collect(words().union(&other).collect());
+                    assert_eq!(union, iter);
+                    assert_eq!(union, Vec::from(words().into_union(other.clone())));
+                    let intersection = strings(&["alpha", "charlie"]);
+                    assert_eq!(intersection, collect(words().intersection(&other).collect()));
+                    assert_eq!(intersection, Vec::from(words().into_intersection(other.clone())));
+                    let difference = strings(&["bravo", "delta"]);
+                    assert_eq!(difference, collect(words().difference(&other).collect()));
+                    assert_eq!(difference, Vec::from(words().into_difference(other.clone())));
+                    let symmetric = strings(&["bravo", "delta", "echo"]);
+                    let iter = words().symmetric_difference(&other).cloned().collect::<Vec<_>>();
+                    assert_eq!(symmetric, iter);
+                    assert_eq!(symmetric, Vec::from(words().into_symmetric_difference(other)));
+                    assert!(!words().is_disjoint(&words()));
+                }
+
+                #[test]
+                fn joins() {
+                    let words = words();
+                    let others = strings(&["alpha", "alpha", "bravo", "echo"]);
+                    let lens = |groups: (&[String], &[String])| (groups.0.len(), groups.1.len());
+                    // Joins pass references to the values of the other side, which are strings.
+                    #[allow(clippy::ptr_arg)]
+                    fn key_of(x: &String) -> $($key)* {
+                        $derive(x)
+                    }
+
+                    let inner: Vec<_> = words.inner_join(&others, key_of).map(lens).collect();
+                    assert_eq!(vec![(1, 2), (1, 1)], inner);
+                    let full: Vec<_> = words.full_join(&others, key_of).map(lens).collect();
+                    assert_eq!(vec![(1, 2), (1, 1), (1, 0), (1, 0), (0, 1)], full);
+                    assert_eq!(4, words.left_join(&others, key_of).count());
+                    assert_eq!(2, words.semi_join(&others, key_of).count());
+                    let anti: Vec<_> = words.anti_join(&others, key_of).collect();
+                    assert_eq!(vec![&strings(&["charlie"])[..], &strings(&["delta"])[..]], anti);
+                }
+
+                #[test]
+                fn diff_and_patch() {
+                    use crate::diff::Change;
+
+                    let old = words();
+                    let new = Words::from(strings(&["alpha", "charlie", "echo", "delta"]));
+                    let patch: Vec<_> = old.diff_by(&new, String::eq).map(Change::cloned).collect();
+                    assert_eq!(
+                        vec![Change::Removed("bravo".to_owned()), Change::Added("echo".to_owned())],
+                        patch
+                    );
+
+                    let mut patched = old.clone();
+                    patched.apply_patch_by(patch, PartialEq::eq);
+                    assert_eq!(&*new, &*patched);
+                }
+
+                #[test]
+                fn rekey() {
+                    let mut words = words();
+                    let idx = words.rekey(&($query)("bravo"), |x| *x = "echo".to_owned());
+                    assert_eq!(Some(3), idx);
+                    assert_eq!(None, words.rekey(&($query)("bravo"), |x| x.clear()));
+                    assert_eq!(0, words.update_key_at(2, |x| *x = "a".to_owned()));
+                    assert_eq!(&strings(&["a", "alpha", "charlie", "echo"]), &*words);
+                }
+
+                #[test]
+                fn cursors() {
+                    let mut words = words();
+                    let mut cursor = words.cursor_at(&($query)("bz"));
+                    assert_eq!(2, cursor.index());
+                    assert_eq!(Some("charlie"), cursor.next().map(|x| &x[..]));
+                    assert_eq!(Some("charlie"), cursor.prev().map(|x| &x[..]));
+                    assert_eq!(Some("bravo"), cursor.peek_prev().map(|x| &x[..]));
+
+                    let mut cursor = words.cursor_mut_at(&($query)("charlie"));
+                    assert_eq!(Some("charlie".to_owned()), cursor.remove_current());
+                    assert_eq!(Ok(()), cursor.insert_before("bz".to_owned()));
+                    assert_eq!(Err("a".to_owned()), cursor.insert_after("a".to_owned()));
+                    assert_eq!(Some("delta"), cursor.next().map(|x| &x[..]));
+                    assert_eq!(&strings(&["alpha", "bravo", "bz", "delta"]), &*words);
+                }
+
+                #[test]
+                fn hinted_and_batched_lookups() {
+                    let mut words = words();
+                    words.insert("bravo".to_owned());
+                    for hint in 0..7 {
+                        assert_eq!(Ok(1), words.position_from_hint(&($query)("bravo"), hint));
+                        assert_eq!(Err(3), words.position_from_hint(&($query)("bz"), hint));
+                    }
+
+                    let keys = [($query)("delta"), ($query)("bravo"), ($query)("zulu")];
+                    assert_eq!(vec![Ok(4), Ok(1), Err(5)], words.positions_many(&keys));
+                    let found: Vec<_> = words
+                        .find_many(&keys)
+                        .into_iter()
+                        .map(|x| x.map(|x| &x[..]))
+                        .collect();
+                    assert_eq!(vec![Some("delta"), Some("bravo"), None], found);
+                }
+            }
+        };
+    }
+
+    conformance!(
+        binary, sortedvec,
+        key = [&str], derive = String::as_str, query = std::convert::identity,
+        collection_key = &'static str
+    );
+
+    conformance!(
+        slice,
+        sortedvec_slicekey,
+        key = [&[u8]],
+        derive = str::as_bytes,
+        query = str::as_bytes,
+        collection_key = [u8]
+    );
+}
@@ -0,0 +1,436 @@
+//! Merge algorithms on sorted slices and vectors that back the set operations and joins of the
+//! generated structs. All functions take comparison functions that are consistent with the
+//! order of the inputs.
+
+use std::cmp::Ordering;
+use std::marker::PhantomData;
+
+use crate::diff::Change;
+
+/// The set operation performed by a [`SetOp`] or [`set_op_owned`].
+///
+/// [`SetOp`]: struct.SetOp.html
+/// [`set_op_owned`]: fn.set_op_owned.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Union,
+    Intersection,
+    Difference,
+    SymmetricDifference,
+}
+
+impl Op {
+    pub fn keeps_left(self) -> bool {
+        self != Op::Intersection
+    }
+
+    pub fn keeps_right(self) -> bool {
+        self == Op::Union || self == Op::SymmetricDifference
+    }
+
+    pub fn keeps_pair(self) -> bool {
+        self == Op::Union || self == Op::Intersection
+    }
+}
+
+/// Returns the number of leading elements of `slice` for which `pred` holds, given that it holds
+/// for a prefix of `slice` only. Uses an exponential search, so that it takes `O(log(n))` time
+/// where `n` is the returned index.
+pub fn gallop<'a, T>(slice: &'a [T], mut pred: impl FnMut(&'a T) -> bool) -> usize {
+    match slice.first() {
+        Some(first) if pred(first) => {}
+        _ => return 0,
+    }
+    let mut step = 1;
+    while step < slice.len() && pred(&slice[step]) {
+        step *= 2;
+    }
+    let mut lo = step / 2 + 1;
+    let mut hi = std::cmp::min(step, slice.len());
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if pred(&slice[mid]) {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    lo
+}
+
+/// Returns the number of trailing elements of `slice` for which `pred` holds, given that it holds
+/// for a suffix of `slice` only. This is the mirror image of [`gallop`].
+///
+/// [`gallop`]: fn.gallop.html
+pub fn gallop_back<'a, T>(slice: &'a [T], mut pred: impl FnMut(&'a T) -> bool) -> usize {
+    let len = slice.len();
+    match slice.last() {
+        Some(last) if pred(last) => {}
+        _ => return 0,
+    }
+    let mut step = 1;
+    while step < len && pred(&slice[len - 1 - step]) {
+        step *= 2;
+    }
+    let mut lo = step / 2 + 1;
+    let mut hi = std::cmp::min(step, len);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if pred(&slice[len - 1 - mid]) {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    lo
+}
+
+/// Hints the processor to load the element of `slice` at `idx` into the cache. Indices past the
+/// end of the slice are harmless.
+#[inline]
+pub fn prefetch<T>(slice: &[T], idx: usize) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        let ptr = slice.as_ptr().wrapping_add(idx);
+        // SAFETY: SSE is part of every `x86_64` target, and prefetching never dereferences the
+        // pointer nor faults, so it need not be in bounds. `wrapping_add` keeps computing it
+        // free of undefined behavior too.
+        unsafe {
+            std::arch::x86_64::_mm_prefetch(ptr as *const i8, std::arch::x86_64::_MM_HINT_T0);
+        }
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        let _ = (slice, idx);
+    }
+}
+
+/// Returns the index of the first element of `slice` for which `is_less` does not hold, given
+/// that it holds for a prefix of `slice` only. The loop has a fixed number of iterations for a
+/// given length, and only the start of the half to continue in depends on the comparison, so
+/// that the compiler can emit a conditional move instead of a branch that would be mispredicted
+/// half the time. Both candidate midpoints of the next iteration are prefetched, hiding memory
+/// latency for slices that do not fit in the cache.
+#[inline]
+pub fn lower_bound<T>(slice: &[T], mut is_less: impl FnMut(&T) -> bool) -> usize {
+    if slice.is_empty() {
+        return 0;
+    }
+    let mut base = 0;
+    let mut size = slice.len();
+    while size > 1 {
+        let half = size / 2;
+        let mid = base + half;
+        prefetch(slice, base + (size - half) / 2);
+        prefetch(slice, mid + (size - half) / 2);
+        // SAFETY: `base + size <= slice.len()` holds throughout the loop and `half < size`, so
+        // `mid` is in bounds.
+        let less = is_less(unsafe { slice.get_unchecked(mid) });
+        base = if less { mid } else { base };
+        size -= half;
+    }
+    base + is_less(&slice[base]) as usize
+}
+
+/// Returns the index of the first element of `slice` for which `is_less` does not hold, given
+/// that it holds for a prefix of `slice` only, like [`lower_bound`]. The index is estimated by
+/// interpolating `target` between the numbers that `num` maps the elements at both ends of the
+/// window to, which must be monotonic in the order of `slice`. After every estimate, its
+/// neighbour on the side of the target is checked as well, to finish exact estimates right
+/// away. When an estimate does not halve the window, the next step bisects it, so that the
+/// search takes `O(log(n))` steps in the worst case.
+///
+/// [`lower_bound`]: fn.lower_bound.html
+pub fn interpolation_search<T>(
+    slice: &[T],
+    target: u64,
+    num: impl Fn(&T) -> u64,
+    is_less: impl Fn(&T) -> bool,
+) -> usize {
+    // The result lies in `lo..=hi`.
+    let (mut lo, mut hi) = (0, slice.len());
+    let mut interpolate = true;
+    while lo < hi {
+        let len = hi - lo;
+        let mid = if interpolate {
+            let (first, last) = (num(&slice[lo]), num(&slice[hi - 1]));
+            if target <= first || last <= first {
+                lo
+            } else if target >= last {
+                hi - 1
+            } else {
+                let offset = u128::from(target - first) * (len - 1) as u128;
+                lo + (offset / u128::from(last - first)) as usize
+            }
+        } else {
+            lo + len / 2
+        };
+        if is_less(&slice[mid]) {
+            lo = mid + 1;
+            if interpolate && lo < hi && !is_less(&slice[lo]) {
+                return lo;
+            }
+        } else {
+            hi = mid;
+            if interpolate && lo < hi && is_less(&slice[hi - 1]) {
+                return hi;
+            }
+        }
+        interpolate = hi - lo <= len / 2;
+    }
+    lo
+}
+
+/// Returns the index of the first element of `slice` for which `is_less` does not hold, given
+/// that it holds for a prefix of `slice` only, by galloping outward from `hint`. This takes
+/// `O(log(d))` time, where `d` is the distance between the hint and the returned index.
+pub fn partition_point_from<T>(slice: &[T], hint: usize, is_less: impl Fn(&T) -> bool) -> usize {
+    let hint = std::cmp::min(hint, slice.len());
+    match slice.get(hint) {
+        Some(probe) if is_less(probe) => hint + 1 + gallop(&slice[hint + 1..], &is_less),
+        _ => hint - gallop_back(&slice[..hint], |probe| !is_less(probe)),
+    }
+}
+
+/// Lazy set operation on two sorted slices. Values with equal keys are paired up in order. Of
+/// every pair, the left value is yielded by unions and intersections and neither is yielded
+/// by (symmetric) differences. Unpaired values are yielded depending on their side and the
+/// operation. Runs of values that are skipped are passed over by galloping, so that
+/// intersections with a small collection take time logarithmic in the size of the other.
+pub struct SetOp<'a, T, C> {
+    left: &'a [T],
+    right: &'a [T],
+    cmp: C,
+    op: Op,
+}
+
+impl<'a, T, C: Fn(&T, &T) -> Ordering> SetOp<'a, T, C> {
+    pub fn new(left: &'a [T], right: &'a [T], cmp: C, op: Op) -> Self {
+        SetOp {
+            left,
+            right,
+            cmp,
+            op,
+        }
+    }
+}
+
+impl<'a, T, C: Fn(&T, &T) -> Ordering> Iterator for SetOp<'a, T, C> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        loop {
+            let (left, right) = match (self.left.split_first(), self.right.split_first()) {
+                (None, None) => return None,
+                (Some((x, rest)), None) => {
+                    self.left = rest;
+                    return self.op.keeps_left().then_some(x);
+                }
+                (None, Some((y, rest))) => {
+                    self.right = rest;
+                    return self.op.keeps_right().then_some(y);
+                }
+                (Some(left), Some(right)) => (left, right),
+            };
+            let ((x, left_rest), (y, right_rest)) = (left, right);
+            let cmp = &self.cmp;
+            match cmp(x, y) {
+                Ordering::Less if self.op.keeps_left() => {
+                    self.left = left_rest;
+                    return Some(x);
+                }
+                Ordering::Less => {
+                    let skip = gallop(self.left, |v| cmp(v, y) == Ordering::Less);
+                    self.left = &self.left[skip..];
+                }
+                Ordering::Greater if self.op.keeps_right() => {
+                    self.right = right_rest;
+                    return Some(y);
+                }
+                Ordering::Greater => {
+                    let skip = gallop(self.right, |v| cmp(x, v) == Ordering::Greater);
+                    self.right = &self.right[skip..];
+                }
+                Ordering::Equal => {
+                    self.left = left_rest;
+                    self.right = right_rest;
+                    if self.op.keeps_pair() {
+                        return Some(x);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Performs a set operation on two sorted vectors, with the same semantics as [`SetOp`].
+///
+/// [`SetOp`]: struct.SetOp.html
+pub fn set_op_owned<T>(
+    left: Vec<T>,
+    right: Vec<T>,
+    cmp: impl Fn(&T, &T) -> Ordering,
+    op: Op,
+) -> Vec<T> {
+    let mut res = Vec::with_capacity(match op {
+        Op::Union | Op::SymmetricDifference => left.len() + right.len(),
+        Op::Intersection | Op::Difference => left.len(),
+    });
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+
+    loop {
+        let ord = match (left.peek(), right.peek()) {
+            (None, None) => break,
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(x), Some(y)) => cmp(x, y),
+        };
+        match ord {
+            Ordering::Less => res.extend(left.next().filter(|_| op.keeps_left())),
+            Ordering::Greater => res.extend(right.next().filter(|_| op.keeps_right())),
+            Ordering::Equal => {
+                let x = left.next();
+                right.next();
+                if op.keeps_pair() {
+                    res.extend(x);
+                }
+            }
+        }
+    }
+
+    res
+}
+
+/// The kind of join performed by a [`Join`].
+///
+/// [`Join`]: struct.Join.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinKind {
+    Inner,
+    LeftOuter,
+    FullOuter,
+}
+
+/// Lazy merge join of two slices that are sorted on keys of the same type. Yields a pair of
+/// groups of values for every key, in order. Keys present on only one side are yielded with an
+/// empty group for the other side, provided that the kind of join keeps that side. Runs of
+/// values that are skipped are passed over by galloping.
+pub struct Join<'a, L, R, K, FL, FR, C> {
+    left: &'a [L],
+    right: &'a [R],
+    left_key: FL,
+    right_key: FR,
+    cmp: C,
+    kind: JoinKind,
+    key: PhantomData<fn() -> K>,
+}
+
+impl<'a, L, R, K, FL, FR, C> Join<'a, L, R, K, FL, FR, C>
+where
+    FL: Fn(&'a L) -> K,
+    FR: Fn(&'a R) -> K,
+    C: Fn(&K, &K) -> Ordering,
+{
+    pub fn new(
+        left: &'a [L],
+        left_key: FL,
+        right: &'a [R],
+        right_key: FR,
+        cmp: C,
+        kind: JoinKind,
+    ) -> Self {
+        Join {
+            left,
+            right,
+            left_key,
+            right_key,
+            cmp,
+            kind,
+            key: PhantomData,
+        }
+    }
+}
+
+impl<'a, L, R, K, FL, FR, C> Iterator for Join<'a, L, R, K, FL, FR, C>
+where
+    FL: Fn(&'a L) -> K,
+    FR: Fn(&'a R) -> K,
+    C: Fn(&K, &K) -> Ordering,
+{
+    type Item = (&'a [L], &'a [R]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (left_key, right_key, cmp) = (&self.left_key, &self.right_key, &self.cmp);
+
+        loop {
+            let (left, right) = (self.left, self.right);
+            let ord = match (left.first(), right.first()) {
+                (None, None) => return None,
+                (Some(_), None) if self.kind == JoinKind::Inner => return None,
+                (None, Some(_)) if self.kind != JoinKind::FullOuter => return None,
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(x), Some(y)) => cmp(&left_key(x), &right_key(y)),
+            };
+            match ord {
+                Ordering::Less if self.kind == JoinKind::Inner => {
+                    let key = right_key(&right[0]);
+                    let skip = gallop(left, |v| cmp(&left_key(v), &key) == Ordering::Less);
+                    self.left = &left[skip..];
+                }
+                Ordering::Less => {
+                    let key = left_key(&left[0]);
+                    let len = gallop(left, |v| cmp(&left_key(v), &key) == Ordering::Equal);
+                    self.left = &left[len..];
+                    return Some((&left[..len], &[]));
+                }
+                Ordering::Greater if self.kind != JoinKind::FullOuter => {
+                    let key = left_key(&left[0]);
+                    let skip = gallop(right, |v| cmp(&right_key(v), &key) == Ordering::Less);
+                    self.right = &right[skip..];
+                }
+                Ordering::Greater => {
+                    let key = right_key(&right[0]);
+                    let len = gallop(right, |v| cmp(&right_key(v), &key) == Ordering::Equal);
+                    self.right = &right[len..];
+                    return Some((&[], &right[..len]));
+                }
+                Ordering::Equal => {
+                    let key = left_key(&left[0]);
+                    let left_len = gallop(left, |v| cmp(&left_key(v), &key) == Ordering::Equal);
+                    let right_len = gallop(right, |v| cmp(&right_key(v), &key) == Ordering::Equal);
+                    self.left = &left[left_len..];
+                    self.right = &right[right_len..];
+                    return Some((&left[..left_len], &right[..right_len]));
+                }
+            }
+        }
+    }
+}
+
+/// Returns whether two groups of values are equal element-wise according to `eq`.
+pub fn groups_eq<T>(old: &[T], new: &[T], eq: impl Fn(&T, &T) -> bool) -> bool {
+    old.len() == new.len() && old.iter().zip(new).all(|(x, y)| eq(x, y))
+}
+
+/// Returns the changes between two groups of values with equal keys, given whether they are
+/// equal, as described by [`Change`].
+///
+/// [`Change`]: ../diff/enum.Change.html
+pub fn diff_groups<'a, T>(
+    old: &'a [T],
+    new: &'a [T],
+    unchanged: bool,
+) -> impl Iterator<Item = Change<&'a T>> {
+    let empty: &'a [T] = &[];
+    let (changed, replaced) = match (old, new) {
+        _ if unchanged => (None, (empty, empty)),
+        ([x], [y]) => (Some(Change::Changed(x, y)), (empty, empty)),
+        _ => (None, (old, new)),
+    };
+    changed
+        .into_iter()
+        .chain(replaced.0.iter().map(Change::Removed))
+        .chain(replaced.1.iter().map(Change::Added))
+}